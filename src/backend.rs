@@ -0,0 +1,91 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine::TexEngine;
+use crate::latexmk::LatexmkBackend;
+use crate::makefile::MakeBackend;
+use crate::manifest::BuildManifest;
+use crate::ninja::NinjaBackend;
+use crate::TeachResult;
+
+// Where converted Markdown problems are written, relative to a component
+// directory.
+pub const MARKDOWN_DIR: &str = "../../.teach/markdown";
+
+// Markdown sources are input through the LaTeX converted from them, this
+// is its path relative to MARKDOWN_DIR.
+pub fn converted_source(source: &str) -> Option<String> {
+    source
+        .strip_suffix(".md")
+        .map(|stem| format!("{}.tex", stem))
+}
+
+// A document generated for an item and the problem sources it inputs,
+// relative to the problems directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentSources {
+    pub stem: String,
+    pub sources: Vec<String>,
+}
+
+pub trait BuildBackend {
+    fn write_sheet(
+        &self,
+        name: &str,
+        root: &Path,
+        documents: &[DocumentSources],
+        engine: &TexEngine,
+        manifest: &mut BuildManifest,
+    ) -> TeachResult<()>;
+
+    fn write_component(
+        &self,
+        root: &Path,
+        items: &[&str],
+        problems_dir: &str,
+        include_dirs: &[&str],
+        engine: &TexEngine,
+        manifest: &mut BuildManifest,
+    ) -> TeachResult<()>;
+
+    fn write_toplevel(
+        &self,
+        path: &Path,
+        components: &[&str],
+        manifest: &mut BuildManifest,
+    ) -> TeachResult<()>;
+
+    fn compile(&self, dir: &Path, item: &str, outputs: &[String]) -> TeachResult<()>;
+
+    // The files written by write_toplevel, write_component and
+    // write_sheet, relative to the directory they are written to.
+    fn toplevel_files(&self) -> Vec<String>;
+
+    fn component_files(&self) -> Vec<String>;
+
+    fn sheet_files(&self, name: &str) -> Vec<String>;
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    #[default]
+    Make,
+    Latexmk,
+    Ninja,
+}
+
+impl BackendKind {
+    pub fn all() -> &'static [BackendKind] {
+        &[BackendKind::Make, BackendKind::Latexmk, BackendKind::Ninja]
+    }
+
+    pub fn backend(self) -> Box<dyn BuildBackend> {
+        match self {
+            BackendKind::Make => Box::new(MakeBackend),
+            BackendKind::Latexmk => Box::new(LatexmkBackend),
+            BackendKind::Ninja => Box::new(NinjaBackend),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::Course;
+use crate::TeachResult;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GroupBy {
+    Prefix,
+    Tag,
+}
+
+impl FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<GroupBy, String> {
+        match s {
+            "prefix" => Ok(GroupBy::Prefix),
+            "tag" => Ok(GroupBy::Tag),
+            other => Err(format!("Unknown grouping {}, use prefix or tag", other)),
+        }
+    }
+}
+
+impl fmt::Display for GroupBy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GroupBy::Prefix => write!(f, "prefix"),
+            GroupBy::Tag => write!(f, "tag"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CatalogueEntry {
+    pub name: String,
+    pub used_in: Vec<String>,
+    pub meta: Vec<(String, String)>,
+}
+
+pub type Catalogue = BTreeMap<String, Vec<CatalogueEntry>>;
+
+// Problems are usually named like `limits-squeeze`, group on the part
+// before the first separator.
+pub fn prefix(name: &str) -> &str {
+    name.split(&['-', '_'][..]).next().unwrap_or(name)
+}
+
+// Where each problem is used, as `component/item`.
+pub fn usage(course: &Course) -> TeachResult<BTreeMap<String, Vec<String>>> {
+    let mut used: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (component, comp) in course.course_file.items.iter() {
+        for (name, item) in comp.items.iter() {
+            for prob in course.get_problems(&item.problems())? {
+                used.entry(prob)
+                    .or_default()
+                    .push(format!("{}/{}", component, name));
+            }
+        }
+    }
+    for items in used.values_mut() {
+        items.sort();
+    }
+    Ok(used)
+}
+
+pub fn build_catalogue(course: &Course, group_by: GroupBy) -> TeachResult<Catalogue> {
+    let used = usage(course)?;
+    let problems_dir = course
+        .path
+        .join(&course.course_file.config.sources.problems);
+    let mut problems: Vec<String> = course
+        .get_problems::<&str>(&[])?
+        .into_iter()
+        .filter(|name| problems_dir.join(name).is_dir())
+        .collect();
+    problems.sort();
+
+    let mut catalogue = Catalogue::new();
+    for name in problems {
+        let meta = course.problem_meta(&name)?;
+
+        let groups = match group_by {
+            GroupBy::Prefix => vec![prefix(&name).to_owned()],
+            GroupBy::Tag if meta.tags.is_empty() => vec![String::from("Untagged")],
+            GroupBy::Tag => meta.tags.clone(),
+        };
+
+        let mut fields = vec![];
+        if !meta.tags.is_empty() {
+            fields.push((String::from("tags"), meta.tags.join(", ")));
+        }
+        for (key, value) in meta.other.iter() {
+            let value = match value.as_str() {
+                Some(s) => s.to_owned(),
+                None => value.to_string(),
+            };
+            fields.push((key.clone(), value));
+        }
+
+        let entry = CatalogueEntry {
+            used_in: used.get(&name).cloned().unwrap_or_default(),
+            name,
+            meta: fields,
+        };
+        for group in groups {
+            catalogue.entry(group).or_default().push(entry.clone());
+        }
+    }
+
+    Ok(catalogue)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefix() {
+        assert_eq!(prefix("limits-squeeze"), "limits");
+        assert_eq!(prefix("series_ratio-test"), "series");
+        assert_eq!(prefix("induction"), "induction");
+    }
+}
@@ -0,0 +1,178 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::info;
+
+use crate::backend::{BackendKind, BuildBackend};
+use crate::manifest::BuildManifest;
+use crate::Course;
+use crate::TeachResult;
+
+const INTERMEDIATE_EXTENSIONS: &[&str] = &[
+    ".aux",
+    ".log",
+    ".out",
+    ".toc",
+    ".synctex.gz",
+    ".fls",
+    ".fdb_latexmk",
+    ".bbl",
+    ".blg",
+    ".bcf",
+    ".run.xml",
+    ".nav",
+    ".snm",
+    ".vrb",
+    ".ninja_log",
+    ".ninja_deps",
+];
+
+fn is_intermediate(path: &Path) -> bool {
+    match path.file_name() {
+        Some(name) => {
+            let name = name.to_string_lossy();
+            INTERMEDIATE_EXTENSIONS
+                .iter()
+                .any(|ext| name.ends_with(ext))
+        }
+        None => false,
+    }
+}
+
+fn collect_intermediates(dir: &Path, found: &mut Vec<PathBuf>) -> TeachResult<()> {
+    for entry in dir.read_dir()? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_intermediates(&path, found)?;
+        } else if is_intermediate(&path) {
+            found.push(path);
+        }
+    }
+    Ok(())
+}
+
+pub fn clean(course: &Course) -> TeachResult<()> {
+    let path = course.path.join(&course.year);
+    if !path.is_dir() {
+        info!("Nothing to clean in {}", path.display());
+        return Ok(());
+    }
+
+    let mut files = vec![];
+    collect_intermediates(&path, &mut files)?;
+    for file in files {
+        info!("Removing {}", file.display());
+        fs::remove_file(&file)?;
+    }
+
+    Ok(())
+}
+
+fn is_hidden(path: &Path) -> bool {
+    matches!(path.file_name(), Some(n) if n.to_string_lossy().starts_with('.'))
+}
+
+// Build files that only other backends write, left behind when the
+// backend is switched.
+fn stale_build_files<F>(course: &Course, dir: &Path, files: F, orphans: &mut Vec<PathBuf>)
+where
+    F: Fn(&dyn BuildBackend) -> Vec<String>,
+{
+    let current = files(course.backend().as_ref());
+    for kind in BackendKind::all() {
+        for file in files(kind.backend().as_ref()) {
+            let path = dir.join(&file);
+            if !current.contains(&file) && path.is_file() && !orphans.contains(&path) {
+                orphans.push(path);
+            }
+        }
+    }
+}
+
+pub fn find_orphans(course: &Course) -> TeachResult<Vec<PathBuf>> {
+    let mut orphans = vec![];
+    let path = course.path.join(&course.year);
+    if !path.is_dir() {
+        return Ok(orphans);
+    }
+    stale_build_files(course, &path, |b| b.toplevel_files(), &mut orphans);
+
+    for entry in path.read_dir()? {
+        let comp_path = entry?.path();
+        if !comp_path.is_dir() || is_hidden(&comp_path) {
+            continue;
+        }
+
+        let comp_name = comp_path.file_name().unwrap().to_string_lossy();
+        let component = match course.course_file.items.get(comp_name.as_ref()) {
+            Some(c) => c,
+            None => {
+                orphans.push(comp_path);
+                continue;
+            }
+        };
+
+        stale_build_files(course, &comp_path, |b| b.component_files(), &mut orphans);
+
+        let outputs: Vec<String> = component
+            .items
+            .iter()
+            .flat_map(|(name, item)| item.outputs(name, &course.course_file.config))
+            .collect();
+
+        for entry in comp_path.read_dir()? {
+            let item_path = entry?.path();
+            if is_hidden(&item_path) {
+                continue;
+            }
+
+            let name = item_path
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned();
+            if item_path.is_dir() {
+                if component.items.contains_key(&name) {
+                    stale_build_files(course, &item_path, |b| b.sheet_files(&name), &mut orphans);
+                } else {
+                    orphans.push(item_path);
+                }
+            } else if name.ends_with(".pdf") && !outputs.contains(&name) {
+                orphans.push(item_path);
+            }
+        }
+    }
+
+    orphans.sort();
+    Ok(orphans)
+}
+
+pub fn prune(course: &Course, delete: bool) -> TeachResult<()> {
+    let orphans = find_orphans(course)?;
+    if orphans.is_empty() {
+        info!("No orphaned output found");
+        return Ok(());
+    }
+
+    for orphan in orphans.iter() {
+        println!("{}", orphan.display());
+    }
+    if !delete {
+        info!("Run with --delete to remove {} path(s)", orphans.len());
+        return Ok(());
+    }
+
+    let year_path = course.path.join(&course.year);
+    let mut manifest = BuildManifest::load(&course.manifest_path(), &year_path)?;
+    for orphan in orphans.iter() {
+        info!("Removing {}", orphan.display());
+        if orphan.is_dir() {
+            fs::remove_dir_all(orphan)?;
+        } else {
+            fs::remove_file(orphan)?;
+        }
+        manifest.forget(orphan);
+    }
+
+    manifest.save()
+}
@@ -1,21 +1,84 @@
-use std::collections::HashMap;
+use std::fmt;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::str::FromStr;
 
 use failure::bail;
 
 use chrono::{self, Datelike};
 use glob;
+use indexmap::IndexMap;
 use log::{info, warn};
 use serde::{Deserialize, Serialize};
 use toml;
 
+use crate::backend::BuildBackend;
 use crate::config::AppConfig;
-use crate::course_items::{Component, Config, Metadata};
-use crate::makefile::write_toplevel_makefile;
+use crate::course_items::{Component, Config, CourseItem, Metadata};
+use crate::diff::unified_diff;
+use crate::html;
+use crate::manifest::{BuildManifest, FileStatus, InputHasher, PlannedFile};
+use crate::markdown;
+use crate::problem::{
+    is_markdown, source_file, ProblemFiles, ProblemMeta, MARKSCHEME, REQUIRED_PARTS,
+};
+use crate::watch::Change;
 use crate::TeachResult;
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector {
+    pub component: String,
+    pub item: Option<String>,
+}
+
+impl Selector {
+    pub fn matches(&self, component: &str, item: &str) -> bool {
+        self.component == component && self.item.iter().all(|i| i == item)
+    }
+}
+
+impl FromStr for Selector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Selector, String> {
+        let s = s.trim_matches('/');
+        if s.is_empty() {
+            return Err(String::from("Empty selector"));
+        }
+
+        let mut parts = s.splitn(2, '/');
+        Ok(Selector {
+            component: parts.next().unwrap_or_default().to_owned(),
+            item: parts.next().map(ToOwned::to_owned),
+        })
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.item {
+            Some(ref item) => write!(f, "{}/{}", self.component, item),
+            None => write!(f, "{}/", self.component),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct BuildOptions {
+    pub selectors: Vec<Selector>,
+    pub compile: bool,
+    pub dry_run: bool,
+    pub show_diff: bool,
+    pub force: bool,
+}
+
+impl BuildOptions {
+    pub fn selects(&self, component: &str, item: &str) -> bool {
+        self.selectors.is_empty() || self.selectors.iter().any(|s| s.matches(component, item))
+    }
+}
+
 #[derive(Debug)]
 pub struct Course {
     pub year: String,
@@ -109,7 +172,18 @@ impl Course {
         Ok(())
     }
 
-    pub fn build(&self) -> TeachResult<()> {
+    pub fn build(&self, options: &BuildOptions) -> TeachResult<()> {
+        for selector in options.selectors.iter() {
+            let found = self.course_file.items.iter().any(|(component, comp)| {
+                comp.items
+                    .keys()
+                    .any(|name| selector.matches(component, name))
+            });
+            if !found {
+                bail!("No items match {}", selector);
+            }
+        }
+
         if !self.path.is_dir() {
             bail!(
                 "Path {} does not exist or is not a directory",
@@ -118,23 +192,368 @@ impl Course {
         }
 
         let path = self.path.join(&self.year);
-        if !path.exists() {
-            fs::create_dir(&path)?;
-        }
+        let mut manifest = BuildManifest::load(&self.manifest_path(), &path)?;
+        manifest.set_dry_run(options.dry_run);
+        manifest.set_force(options.force);
+        manifest.create_dir(&path)?;
 
         let mut p: PathBuf;
 
+        if !options.dry_run {
+            for (component, comp) in self.course_file.items.iter() {
+                for (name, item) in comp.items.iter() {
+                    if options.selects(component, name) {
+                        self.convert_markdown(&self.get_problems(&item.problems())?)?;
+                    }
+                }
+            }
+        }
+
         for (component, item) in self.course_file.items.iter() {
             p = path.join(component);
             info!("Creating {}", p.display());
-            if !p.exists() {
-                fs::create_dir(&p)?;
+            manifest.create_dir(&p)?;
+            item.build(component, &p, &self, options, &mut manifest)?;
+        }
+
+        let components: Vec<&str> = self.course_file.items.keys().map(AsRef::as_ref).collect();
+        self.backend()
+            .write_toplevel(&path, &components, &mut manifest)?;
+        if self.course_file.config.build.html {
+            html::write_index(&path, self, &mut manifest)?;
+        }
+
+        if options.dry_run {
+            Course::report_dry_run(&manifest, options.show_diff);
+            return Ok(());
+        }
+
+        manifest.save()?;
+
+        let protected = manifest.protected();
+        if !protected.is_empty() {
+            for file in protected {
+                Course::print_planned(&manifest, file, options.show_diff);
+            }
+            bail!(
+                "{} generated file(s) edited by hand, use --force to overwrite",
+                protected.len()
+            );
+        }
+
+        if options.compile {
+            self.compile_matching(|component, name, _| Ok(options.selects(component, name)))?;
+        }
+
+        Ok(())
+    }
+
+    fn print_planned(manifest: &BuildManifest, file: &PlannedFile, show_diff: bool) {
+        let rel = manifest.relative(&file.path);
+        println!("{:>11}: {}", file.status, rel.display());
+        if show_diff {
+            let old_name = format!("a/{}", rel.display());
+            let new_name = format!("b/{}", rel.display());
+            print!(
+                "{}",
+                unified_diff(&file.old, &file.new, &old_name, &new_name)
+            );
+        }
+    }
+
+    fn report_dry_run(manifest: &BuildManifest, show_diff: bool) {
+        let mut counts = [0usize; 4];
+        for file in manifest.planned() {
+            match file.status {
+                FileStatus::Created => counts[0] += 1,
+                FileStatus::Modified => counts[1] += 1,
+                FileStatus::HandEdited => counts[3] += 1,
+                FileStatus::Unchanged => {
+                    counts[2] += 1;
+                    continue;
+                }
+            }
+
+            Course::print_planned(manifest, file, show_diff);
+        }
+
+        println!(
+            "{} created, {} modified, {} unchanged, {} edited by hand",
+            counts[0], counts[1], counts[2], counts[3]
+        );
+    }
+
+    fn compile_matching<F>(&self, mut matches: F) -> TeachResult<()>
+    where
+        F: FnMut(&str, &str, &CourseItem) -> TeachResult<bool>,
+    {
+        let path = self.path.join(&self.year);
+        let backend = self.backend();
+        for (component, comp) in self.course_file.items.iter() {
+            for (name, item) in comp.items.iter() {
+                if matches(component, name, item)? {
+                    backend.compile(
+                        &path.join(component),
+                        name,
+                        &item.outputs(name, &self.course_file.config),
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn rebuild(&self, change: &Change) -> TeachResult<()> {
+        self.build(&BuildOptions::default())?;
+
+        self.compile_matching(|_, _, item| {
+            let problems = self.get_problems(&item.problems())?;
+            Ok(change.affects_any(&problems))
+        })
+    }
+
+    pub fn find_item(&self, component: &str, name: &str) -> TeachResult<(&Component, &CourseItem)> {
+        let comp = match self.course_file.items.get(component) {
+            Some(comp) => comp,
+            None => bail!("No component named {}", component),
+        };
+        match comp.items.get(name) {
+            Some(item) => Ok((comp, item)),
+            None => bail!("No item named {}/{}", component, name),
+        }
+    }
+
+    pub fn backend(&self) -> Box<dyn BuildBackend> {
+        self.course_file.config.build.backend.backend()
+    }
+
+    pub fn state_dir(&self) -> PathBuf {
+        self.path.join(".teach")
+    }
+
+    pub fn manifest_path(&self) -> PathBuf {
+        let name = format!("manifest-{}.toml", &self.year);
+        self.state_dir().join(name)
+    }
+
+    pub fn problem_meta(&self, problem: &str) -> TeachResult<ProblemMeta> {
+        ProblemMeta::load(
+            &self
+                .path
+                .join(&self.course_file.config.sources.problems)
+                .join(problem),
+        )
+    }
+
+    // Queries are problem names, globs or `tag:<tag>`. Matches are sorted
+    // and each problem is listed once.
+    pub fn find_problems<S: AsRef<str>>(&self, queries: &[S]) -> TeachResult<Vec<String>> {
+        let mut rv: Vec<String> = vec![];
+        let mut all: Option<Vec<String>> = None;
+
+        for query in queries {
+            let query = query.as_ref();
+            if let Some(tag) = query.strip_prefix("tag:") {
+                if all.is_none() {
+                    all = Some(self.get_problems::<&str>(&[])?);
+                }
+                for prob in all.as_ref().unwrap() {
+                    if self.problem_meta(prob)?.has_tag(tag) {
+                        rv.push(prob.clone());
+                    }
+                }
+            } else {
+                rv.extend(self.get_problems(&[query])?);
+            }
+        }
+
+        rv.sort();
+        rv.dedup();
+        Ok(rv)
+    }
+
+    pub fn problem_dir(&self, problem: &str) -> PathBuf {
+        self.path
+            .join(&self.course_file.config.sources.problems)
+            .join(problem)
+    }
+
+    // Optional parts, like mark schemes or hints, are left out for
+    // problems that do not have them.
+    pub fn part_source(&self, problem: &str, part: &str) -> Option<PathBuf> {
+        let path = source_file(&self.problem_dir(problem), part);
+        if path.is_file() || REQUIRED_PARTS.contains(&part) {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    fn part_file(&self, problem: &str, part: &str) -> Option<String> {
+        self.part_source(problem, part).map(|path| {
+            let file = path.file_name().unwrap_or_default().to_string_lossy();
+            format!("{}/{}", problem, file)
+        })
+    }
+
+    // The parts a problem has out of those given.
+    pub fn problem_parts<S: AsRef<str>>(&self, problem: &str, parts: &[S]) -> Vec<String> {
+        parts
+            .iter()
+            .map(AsRef::as_ref)
+            .filter(|part| self.part_source(problem, part).is_some())
+            .map(String::from)
+            .collect()
+    }
+
+    pub fn problem_part_files<S: AsRef<str>>(&self, problem: &str, parts: &[S]) -> Vec<String> {
+        parts
+            .iter()
+            .filter_map(|part| self.part_file(problem, part.as_ref()))
+            .collect()
+    }
+
+    fn all_parts(&self) -> Vec<&str> {
+        let mut parts = REQUIRED_PARTS.to_vec();
+        for part in self.course_file.config.parts() {
+            if !parts.contains(&part) {
+                parts.push(part);
+            }
+        }
+        parts
+    }
+
+    pub fn problem_sources(&self, problem: &str) -> Vec<PathBuf> {
+        self.all_parts()
+            .into_iter()
+            .filter_map(|part| self.part_source(problem, part))
+            .collect()
+    }
+
+    pub fn problem_files(&self, problem: &str) -> ProblemFiles {
+        ProblemFiles {
+            problem: self.part_file(problem, "problem").unwrap_or_default(),
+            solution: self.part_file(problem, "solution").unwrap_or_default(),
+            markscheme: self.part_file(problem, MARKSCHEME),
+        }
+    }
+
+    // LaTeX for one part of a problem, converting Markdown sources.
+    pub fn problem_latex(&self, problem: &str, part: &str) -> TeachResult<String> {
+        let path = source_file(&self.problem_dir(problem), part);
+        let source = fs::read_to_string(&path)?;
+        if is_markdown(&path) {
+            Ok(markdown::to_latex(&source, problem))
+        } else {
+            Ok(source)
+        }
+    }
+
+    pub fn markdown_dir(&self) -> PathBuf {
+        self.state_dir().join("markdown")
+    }
+
+    // Markdown sources are converted into the markdown directory, which
+    // is on TEXINPUTS, so documents can input them like LaTeX sources.
+    pub fn convert_markdown<S: AsRef<str>>(&self, problems: &[S]) -> TeachResult<()> {
+        for prob in problems {
+            let prob = prob.as_ref();
+            for part in self.all_parts() {
+                if !is_markdown(&source_file(&self.problem_dir(prob), part)) {
+                    continue;
+                }
+
+                let dest = self.markdown_dir().join(prob).join(format!("{}.tex", part));
+                let latex = self.problem_latex(prob, part)?;
+                if fs::read_to_string(&dest).ok().as_ref() == Some(&latex) {
+                    continue;
+                }
+
+                info!("Converting {}/{}.md", prob, part);
+                fs::create_dir_all(self.markdown_dir().join(prob))?;
+                fs::write(&dest, latex)?;
             }
-            item.build(&p, &self)?;
         }
+        Ok(())
+    }
+
+    // Convert one Markdown source, as the generated build rules do when it
+    // changes. The result is always rewritten, so that make sees it as
+    // newer than the source, and renamed into place because parallel
+    // builds may be reading it.
+    pub fn convert_markdown_file(&self, path: &Path) -> TeachResult<()> {
+        let problems = self
+            .path
+            .join(&self.course_file.config.sources.problems)
+            .canonicalize()?;
+        let path = path.canonicalize()?;
+        let rel = match path.strip_prefix(&problems) {
+            Ok(rel) if is_markdown(&path) => rel,
+            _ => bail!("{} is not a Markdown problem source", path.display()),
+        };
 
-        let components: Vec<&String> = self.course_file.items.keys().collect();
-        write_toplevel_makefile(&path, components.as_slice())?;
+        let dest = self.markdown_dir().join(rel).with_extension("tex");
+        let dir = match dest.parent() {
+            Some(dir) => dir,
+            None => bail!("Cannot convert {}", path.display()),
+        };
+        fs::create_dir_all(dir)?;
+
+        info!("Converting {}", rel.display());
+        let problem = rel
+            .parent()
+            .map_or(String::new(), |p| p.to_string_lossy().into_owned());
+        let latex = markdown::to_latex(&fs::read_to_string(&path)?, &problem);
+        let tmp = dest.with_extension(format!("tex.{}", std::process::id()));
+        fs::write(&tmp, latex)?;
+        fs::rename(&tmp, &dest)?;
+        Ok(())
+    }
+
+    // Whether any Markdown part of the problems has an image, which needs
+    // graphicx in the document inputting it.
+    pub fn uses_graphics<S: AsRef<str>>(&self, problems: &[S]) -> TeachResult<bool> {
+        for prob in problems {
+            for source in self.problem_sources(prob.as_ref()) {
+                if is_markdown(&source)
+                    && source.is_file()
+                    && markdown::has_images(&fs::read_to_string(&source)?)
+                {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    pub fn hash_sources<S: AsRef<str>>(
+        &self,
+        hasher: &mut InputHasher,
+        problems: &[S],
+    ) -> TeachResult<()> {
+        for prob in problems {
+            for source in self.problem_sources(prob.as_ref()) {
+                hasher.file(&source)?;
+            }
+        }
+
+        if let Some(ref bib) = self.course_file.config.bibliography {
+            hasher.file(&self.path.join(&bib.file))?;
+        }
+
+        let include = self.path.join("include");
+        if include.is_dir() {
+            let mut files: Vec<PathBuf> = include
+                .read_dir()?
+                .filter_map(|de| de.ok().map(|e| e.path()))
+                .filter(|p| p.is_file())
+                .collect();
+            files.sort();
+            for file in files {
+                hasher.file(&file)?;
+            }
+        }
 
         Ok(())
     }
@@ -175,13 +594,138 @@ pub struct CourseFile {
     #[serde(flatten)]
     pub config: Config,
 
+    // Components and items keep the order of course.toml.
     #[serde(flatten)]
-    pub items: HashMap<String, Component>,
+    pub items: IndexMap<String, Component>,
+}
+
+// Top-level tables read into Config, which components cannot be named after.
+const CONFIG_SECTIONS: &[&str] = &[
+    "metadata",
+    "sources",
+    "bibliography",
+    "build",
+    "tex",
+    "sheets",
+    "solutions",
+    "courseworks",
+    "documents",
+    "editions",
+];
+
+// A component named after a configuration section would be read as that
+// section and its items silently dropped, so look for item tables there.
+fn check_component_names(contents: &str) -> TeachResult<()> {
+    let value: toml::Value = toml::from_str(contents)?;
+    for section in CONFIG_SECTIONS {
+        let table = match value.get(section).and_then(toml::Value::as_table) {
+            Some(table) => table,
+            None => continue,
+        };
+        if table
+            .values()
+            .any(|v| v.get("problems").is_some() || v.get("sections").is_some())
+        {
+            bail!(
+                "`{}` is a configuration section, components cannot use that name",
+                section
+            );
+        }
+    }
+    Ok(())
 }
 
 impl CourseFile {
     pub fn load(path: &Path) -> TeachResult<CourseFile> {
-        let cf = toml::from_str(&fs::read_to_string(path)?)?;
+        let contents = fs::read_to_string(path)?;
+        check_component_names(&contents)?;
+        let mut cf: CourseFile = toml::from_str(&contents)?;
+        if cf.config.editions.is_empty() {
+            cf.config.editions = cf.config.default_editions();
+        }
         Ok(cf)
     }
 }
+
+// A course in a temporary directory for tests. `tables` follow the required
+// sections of course.toml and `files` are written relative to the root.
+#[cfg(test)]
+pub(crate) fn test_course(tables: &str, files: &[(&str, &str)]) -> (tempfile::TempDir, Course) {
+    let dir = tempfile::TempDir::new().unwrap();
+    let course_file = format!(
+        "[metadata]\nauthor = \"A\"\n[sources]\nproblems = \"problems\"\n{}",
+        tables
+    );
+    for (path, contents) in [("course.toml", course_file.as_str())].iter().chain(files) {
+        let path = dir.path().join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+    let course = Course::load(dir.path()).unwrap();
+    (dir, course)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_course_order() {
+        let cf: CourseFile = toml::from_str(
+            "[metadata]\nauthor = \"JM\"\n[sources]\nproblems = \"problems\"\n\
+             [tutorials.week2]\ntitle = \"B\"\ntopic = \"b\"\nproblems = []\n\
+             [tutorials.week1]\ntitle = \"A\"\ntopic = \"a\"\nproblems = []\n\
+             [assessed.cw1]\ntitle = \"C\"\ntopic = \"c\"\nproblems = []\nmarks = []\n",
+        )
+        .unwrap();
+        let components: Vec<&String> = cf.items.keys().collect();
+        assert_eq!(components, vec!["tutorials", "assessed"]);
+        let items: Vec<&String> = cf.items["tutorials"].items.keys().collect();
+        assert_eq!(items, vec!["week2", "week1"]);
+    }
+
+    #[test]
+    fn test_reserved_component_names() {
+        let head = "[metadata]\nauthor = \"JM\"\n[sources]\nproblems = \"problems\"\n";
+        assert!(check_component_names(&format!(
+            "{}[sheets]\ndocument_class = \"article\"\n\
+             [tutorials.week1]\ntitle = \"A\"\ntopic = \"a\"\nproblems = []\n",
+            head
+        ))
+        .is_ok());
+
+        let err = check_component_names(&format!(
+            "{}[sheets.week1]\ntitle = \"A\"\ntopic = \"a\"\nproblems = []\n",
+            head
+        ))
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "`sheets` is a configuration section, components cannot use that name"
+        );
+
+        // Exam papers are configured under [documents], so exams can be a
+        // component.
+        assert!(check_component_names(&format!(
+            "{}[documents.exams]\ndocument_class = \"article\"\n\
+             [exams.final]\ntitle = \"A\"\ntopic = \"a\"\nduration = 60\n\
+             [[exams.final.sections]]\ntitle = \"S\"\nproblems = []\nmarks = []\n",
+            head
+        ))
+        .is_ok());
+    }
+
+    #[test]
+    fn test_parse_selector() {
+        let sel: Selector = "tutorials/week3".parse().unwrap();
+        assert!(sel.matches("tutorials", "week3"));
+        assert!(!sel.matches("tutorials", "week4"));
+
+        let sel: Selector = "courseworks/".parse().unwrap();
+        assert_eq!(sel.item, None);
+        assert!(sel.matches("courseworks", "cw1"));
+        assert!(!sel.matches("tutorials", "cw1"));
+
+        assert!("/".parse::<Selector>().is_err());
+    }
+}
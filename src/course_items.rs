@@ -1,14 +1,24 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::fs;
 use std::ops::Deref;
 use std::path::{Path, PathBuf};
 
+use failure::bail;
+use indexmap::IndexMap;
 use latex;
 use log::info;
 use serde::{Deserialize, Serialize};
 
-use crate::latexdoc::{make_coursework_sheet, make_problem_sheet};
-use crate::makefile::{write_component_makefile, write_sheet_makefile};
+use crate::backend::{BackendKind, DocumentSources, MARKDOWN_DIR};
+use crate::course::BuildOptions;
+use crate::engine::{BibTool, TexEngine};
+use crate::html::write_item_pages;
+use crate::latexdoc::{
+    add_bibliography, add_graphicx, make_edition_sheet, make_exam, CourseworkCover, ExamPaper,
+    PaperSection, SheetEntry,
+};
+use crate::manifest::{BuildManifest, InputHasher};
+use crate::problem::MARKSCHEME;
 use crate::Course;
 use crate::TeachResult;
 
@@ -20,10 +30,43 @@ pub struct Sources {
     pub other: HashMap<String, String>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct BuildConfig {
+    #[serde(default)]
+    pub backend: BackendKind,
+
+    #[serde(default)]
+    pub html: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct TexConfig {
+    pub engine: Option<String>,
+    pub flags: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BibConfig {
+    pub file: String,
+
+    #[serde(default)]
+    pub tool: BibTool,
+
+    pub style: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Config {
     pub sources: Sources,
 
+    pub bibliography: Option<BibConfig>,
+
+    #[serde(default)]
+    pub build: BuildConfig,
+
+    #[serde(default)]
+    pub tex: TexConfig,
+
     #[serde(rename = "sheets", default)]
     pub sheet_config: SheetConfig,
 
@@ -32,13 +75,173 @@ pub struct Config {
 
     #[serde(rename = "courseworks", default)]
     pub coursework_config: SheetConfig,
+
+    #[serde(default)]
+    pub documents: DocumentConfig,
+
+    #[serde(default)]
+    pub editions: Vec<Edition>,
 }
 
+// The documents other than sheets are configured under [documents], e.g.
+// [documents.exams], leaving those names free for components.
 #[derive(Serialize, Deserialize, Debug, Default)]
+pub struct DocumentConfig {
+    #[serde(default)]
+    pub markers: SheetConfig,
+
+    #[serde(default)]
+    pub handbook: SheetConfig,
+
+    #[serde(default)]
+    pub exams: SheetConfig,
+}
+
+impl Config {
+    // Levels are given most specific first, the course level is added last.
+    pub fn engine(&self, levels: &[&TexConfig]) -> TexEngine {
+        let mut levels = levels.to_vec();
+        levels.push(&self.tex);
+
+        let mut engine = TexEngine::resolve(&levels);
+        if let Some(ref bib) = self.bibliography {
+            engine.bib_tool = bib.tool;
+        }
+        engine
+    }
+
+    // Courses that declare no editions get problems, solutions and a
+    // marker's edition, typeset with the [sheets], [solutions] and
+    // [documents.markers] sections.
+    pub fn default_editions(&self) -> Vec<Edition> {
+        vec![
+            Edition::new("problems", None, &["problem"], &self.sheet_config),
+            Edition::new(
+                "solutions",
+                Some("Solutions"),
+                &["solution"],
+                &self.solution_config,
+            ),
+            Edition {
+                marks: true,
+                ..Edition::new(
+                    "markers",
+                    Some("Marker's edition"),
+                    &["solution", MARKSCHEME],
+                    &self.documents.markers,
+                )
+            },
+        ]
+    }
+
+    // Exams always get the paper, a separate cover page and the solutions
+    // with the mark scheme.
+    pub fn exam_editions(&self) -> Vec<Edition> {
+        vec![
+            Edition::new("paper", None, &["problem"], &self.documents.exams),
+            Edition {
+                cover: true,
+                ..Edition::new("cover", None, &[], &self.documents.exams)
+            },
+            Edition {
+                marks: true,
+                ..Edition::new(
+                    "solutions",
+                    Some("Solutions and mark scheme"),
+                    &["solution", MARKSCHEME],
+                    &self.documents.markers,
+                )
+            },
+        ]
+    }
+
+    // Every problem part some edition inputs, exam solutions always have
+    // the mark scheme.
+    pub fn parts(&self) -> Vec<&str> {
+        let mut parts: Vec<&str> = vec![];
+        let all = self.editions.iter().flat_map(|e| e.parts.iter());
+        for part in all.map(String::as_str).chain(Some(MARKSCHEME)) {
+            if !parts.contains(&part) {
+                parts.push(part);
+            }
+        }
+        parts
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct SheetConfig {
     pub document_class: Option<String>,
     pub problem_macro: Option<String>,
     pub include_preamble: Option<String>,
+
+    // Only read from `courseworks`, the cover page is on by default.
+    pub cover_page: Option<bool>,
+    pub cover_fields: Option<Vec<String>>,
+}
+
+impl SheetConfig {
+    pub fn cover_fields(&self) -> Vec<String> {
+        match self.cover_fields {
+            Some(ref fields) => fields.clone(),
+            None => vec![String::from("Name"), String::from("Student ID")],
+        }
+    }
+}
+
+// A document generated for every item, inputting the listed parts of each
+// problem, e.g. `problem.tex` and `hints.tex`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Edition {
+    pub name: String,
+
+    // Defaults to the name.
+    pub suffix: Option<String>,
+
+    // Added to the item title, e.g. "Solutions".
+    pub title: Option<String>,
+
+    pub parts: Vec<String>,
+
+    // Editions with marks get a table of marks and are only generated
+    // for items that carry marks.
+    #[serde(default)]
+    pub marks: bool,
+
+    // Exam editions with a cover open with the cover page.
+    #[serde(default)]
+    pub cover: bool,
+
+    #[serde(flatten)]
+    pub sheet_config: SheetConfig,
+}
+
+impl Edition {
+    pub fn new(name: &str, title: Option<&str>, parts: &[&str], config: &SheetConfig) -> Edition {
+        Edition {
+            name: name.to_owned(),
+            suffix: None,
+            title: title.map(String::from),
+            parts: parts.iter().map(|p| String::from(*p)).collect(),
+            marks: false,
+            cover: false,
+            sheet_config: config.clone(),
+        }
+    }
+
+    pub fn suffix(&self) -> &str {
+        self.suffix.as_ref().unwrap_or(&self.name)
+    }
+
+    pub fn stem(&self, item: &str) -> String {
+        format!("{}-{}", item, self.suffix())
+    }
+
+    // Marks go with the problems and the mark scheme. A solution macro is
+    // called without them.
+    pub fn shows_marks(&self) -> bool {
+        self.marks || self.parts.iter().any(|p| p == "problem")
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -63,6 +266,9 @@ pub struct SheetInfo {
     pub topic: String,
     pub intro: Option<String>,
     pub problems: Vec<String>,
+
+    #[serde(default)]
+    pub tex: TexConfig,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -70,86 +276,371 @@ pub struct CourseworkInfo {
     pub title: String,
     pub topic: String,
     pub intro: Option<String>,
+    pub due: Option<String>,
+    pub problems: Vec<String>,
+    pub marks: Vec<u32>,
+
+    #[serde(default)]
+    pub tex: TexConfig,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ExamSection {
+    pub title: String,
     pub problems: Vec<String>,
     pub marks: Vec<u32>,
+
+    // Candidates answer this many of the problems, all of them if unset.
+    pub answer: Option<usize>,
+
+    // Suggested time in minutes.
+    pub time: Option<u32>,
+}
+
+impl ExamSection {
+    // A choice only adds up when every problem is worth the same.
+    pub fn total_marks(&self) -> TeachResult<u32> {
+        let answer = match self.answer {
+            Some(n) => n,
+            None => return Ok(self.marks.iter().sum()),
+        };
+        if answer == 0 || answer > self.marks.len() {
+            bail!(
+                "{}: cannot answer {} of {} problems",
+                self.title,
+                answer,
+                self.marks.len()
+            );
+        }
+        if self.marks.iter().any(|m| *m != self.marks[0]) {
+            bail!(
+                "{}: problems to choose from must carry equal marks, got {:?}",
+                self.title,
+                self.marks
+            );
+        }
+        Ok(self.marks[0] * answer as u32)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ExamInfo {
+    pub title: String,
+    pub topic: String,
+
+    // In minutes.
+    pub duration: u32,
+
+    pub instructions: Option<String>,
+
+    // Checked against the sections when given.
+    pub total_marks: Option<u32>,
+
+    pub sections: Vec<ExamSection>,
+
+    #[serde(default)]
+    pub tex: TexConfig,
+}
+
+impl ExamInfo {
+    // Sections with their problems resolved, checking that marks and
+    // times add up.
+    pub fn resolve(&self, course: &Course) -> TeachResult<Vec<(&ExamSection, Vec<String>)>> {
+        let mut sections = vec![];
+        let mut total = 0;
+        let mut time = 0;
+        for section in self.sections.iter() {
+            let problems = course.get_problems(&section.problems)?;
+            if problems.len() != section.marks.len() {
+                bail!(
+                    "{}: {} problems but {} marks",
+                    section.title,
+                    problems.len(),
+                    section.marks.len()
+                );
+            }
+            total += section.total_marks()?;
+            time += section.time.unwrap_or(0);
+            sections.push((section, problems));
+        }
+
+        if let Some(expected) = self.total_marks {
+            if total != expected {
+                bail!(
+                    "{}: sections add up to {} marks, expected {}",
+                    self.title,
+                    total,
+                    expected
+                );
+            }
+        }
+        if time > self.duration {
+            bail!(
+                "{}: sections take {} minutes but the exam lasts {}",
+                self.title,
+                time,
+                self.duration
+            );
+        }
+        Ok(sections)
+    }
 }
 
+// Exams are tried first, sheets have the fewest required fields.
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(untagged)]
 pub enum CourseItem {
+    Exam(ExamInfo),
     Coursework(CourseworkInfo),
     Sheet(SheetInfo),
 }
 
+fn exam_documents(
+    info: &ExamInfo,
+    name: &str,
+    course: &Course,
+) -> TeachResult<Vec<(String, latex::Document)>> {
+    let sections = info.resolve(course)?;
+    let mut documents = vec![];
+    for edition in course.course_file.config.exam_editions() {
+        let mut paper_sections = vec![];
+        for (section, problems) in sections.iter() {
+            paper_sections.push(PaperSection {
+                title: &section.title,
+                answer: section.answer,
+                time: section.time,
+                marks: section.total_marks()?,
+                entries: problems
+                    .iter()
+                    .zip(section.marks.iter())
+                    .map(|(problem, marks)| SheetEntry {
+                        problem,
+                        parts: course.problem_parts(problem, &edition.parts),
+                        marks: Some(*marks),
+                    })
+                    .collect(),
+            });
+        }
+
+        let paper = ExamPaper {
+            title: match edition.title {
+                Some(ref title) => format!("{} -- {}", info.title, title),
+                None => info.title.clone(),
+            },
+            duration: info.duration,
+            instructions: info.instructions.as_ref().map_or("", String::as_str),
+            sections: paper_sections,
+        };
+        let doc = make_exam(&paper, &course.year, &course.course_file.metadata, &edition);
+        documents.push((edition.stem(name), doc));
+    }
+    Ok(documents)
+}
+
+fn write_document<S: AsRef<str>>(
+    path: &Path,
+    mut doc: latex::Document,
+    problems: &[S],
+    course: &Course,
+    manifest: &mut BuildManifest,
+) -> TeachResult<()> {
+    if course.uses_graphics(problems)? {
+        add_graphicx(&mut doc);
+    }
+    if let Some(ref bib) = course.course_file.config.bibliography {
+        // Documents are compiled from the component directory.
+        add_bibliography(&mut doc, bib, &format!("../../{}", &bib.file));
+    }
+    let contents = latex::print(&doc)?;
+
+    let mut hasher = InputHasher::new();
+    hasher.update(&contents);
+    course.hash_sources(&mut hasher, problems)?;
+
+    manifest.write(path, &hasher.finish(), &contents)?;
+    Ok(())
+}
+
 impl CourseItem {
-    fn build(&self, name: &str, root: &Path, course: &Course) -> TeachResult<()> {
+    // Exam problems are listed section by section.
+    pub fn problems(&self) -> Vec<&str> {
         match self {
-            Self::Sheet(info) => {
-                let intro = match info.intro {
-                    Some(ref t) => t,
-                    None => "",
-                };
-
-                let problems = course.get_problems(&info.problems)?;
-
-                fs::write(
-                    root.join(format!("{}-problems.tex", name)),
-                    latex::print(&make_problem_sheet(
-                        &info.title,
-                        intro,
-                        &course.year,
-                        &course.course_file.metadata,
-                        &problems,
-                        &course.course_file.config.sheet_config,
-                    ))?,
-                )?;
-                fs::write(
-                    root.join(format!("{}-solutions.tex", name)),
-                    latex::print(&make_problem_sheet(
-                        &format!("{} -- Solutions", &info.title),
-                        intro,
-                        &course.year,
-                        &course.course_file.metadata,
-                        &problems,
-                        &course.course_file.config.solution_config,
-                    ))?,
-                )?;
-                write_sheet_makefile(name, root, &problems)?;
-            }
+            Self::Sheet(info) => info.problems.iter().map(String::as_str).collect(),
+            Self::Coursework(info) => info.problems.iter().map(String::as_str).collect(),
+            Self::Exam(info) => info
+                .sections
+                .iter()
+                .flat_map(|s| s.problems.iter().map(String::as_str))
+                .collect(),
+        }
+    }
 
-            Self::Coursework(info) => {
-                let intro = match info.intro {
-                    Some(ref t) => t,
-                    None => "",
-                };
-
-                let problems = course.get_problems(&info.problems)?;
-
-                fs::write(
-                    root.join(format!("{}-problems.tex", name)),
-                    latex::print(&make_coursework_sheet(
-                        &info.title,
-                        intro,
-                        &course.year,
-                        &course.course_file.metadata,
-                        &problems,
-                        &info.marks,
-                        &course.course_file.config.sheet_config,
-                    ))?,
-                )?;
-                fs::write(
-                    root.join(format!("{}-solutions.tex", name)),
-                    latex::print(&make_problem_sheet(
-                        &format!("{} -- Solutions", &info.title),
-                        intro,
-                        &course.year,
-                        &course.course_file.metadata,
-                        &problems,
-                        &course.course_file.config.solution_config,
-                    ))?,
-                )?;
-                write_sheet_makefile(name, root, &problems)?;
+    pub fn title(&self) -> &str {
+        match self {
+            Self::Sheet(info) => &info.title,
+            Self::Coursework(info) => &info.title,
+            Self::Exam(info) => &info.title,
+        }
+    }
+
+    pub fn topic(&self) -> &str {
+        match self {
+            Self::Sheet(info) => &info.topic,
+            Self::Coursework(info) => &info.topic,
+            Self::Exam(info) => &info.topic,
+        }
+    }
+
+    pub fn intro(&self) -> &str {
+        let intro = match self {
+            Self::Sheet(info) => &info.intro,
+            Self::Coursework(info) => &info.intro,
+            Self::Exam(info) => &info.instructions,
+        };
+        intro.as_ref().map_or("", String::as_str)
+    }
+
+    // Sheets carry no marks.
+    pub fn marks(&self) -> Vec<u32> {
+        match self {
+            Self::Sheet(_) => vec![],
+            Self::Coursework(info) => info.marks.clone(),
+            Self::Exam(info) => info
+                .sections
+                .iter()
+                .flat_map(|s| s.marks.iter().cloned())
+                .collect(),
+        }
+    }
+
+    pub fn tex(&self) -> &TexConfig {
+        match self {
+            Self::Sheet(info) => &info.tex,
+            Self::Coursework(info) => &info.tex,
+            Self::Exam(info) => &info.tex,
+        }
+    }
+
+    pub fn editions<'a>(&self, config: &'a Config) -> Vec<Cow<'a, Edition>> {
+        if let Self::Exam(_) = self {
+            return config.exam_editions().into_iter().map(Cow::Owned).collect();
+        }
+        let has_marks = !self.marks().is_empty();
+        config
+            .editions
+            .iter()
+            .filter(|edition| has_marks || !edition.marks)
+            .map(Cow::Borrowed)
+            .collect()
+    }
+
+    pub fn outputs(&self, name: &str, config: &Config) -> Vec<String> {
+        self.editions(config)
+            .iter()
+            .map(|edition| format!("{}.pdf", edition.stem(name)))
+            .collect()
+    }
+
+    // The documents generated for an item, keyed by file stem. Paths in
+    // them are relative to the problems directory, the bibliography is
+    // added by the caller.
+    pub fn documents(
+        &self,
+        name: &str,
+        course: &Course,
+    ) -> TeachResult<Vec<(String, latex::Document)>> {
+        let config = &course.course_file.config;
+        if let Self::Exam(info) = self {
+            return exam_documents(info, name, course);
+        }
+        let problems = course.get_problems(&self.problems())?;
+        let marks = self.marks();
+
+        // Courseworks open with a cover page in the editions handed to
+        // students.
+        let cover_fields = config.coursework_config.cover_fields();
+        let due = match self {
+            Self::Coursework(info) if config.coursework_config.cover_page.unwrap_or(true) => {
+                Some(info.due.as_deref())
             }
+            _ => None,
+        };
+
+        let mut documents = vec![];
+        for edition in self.editions(config) {
+            let cover = match due {
+                Some(due) if !edition.marks && edition.parts.iter().any(|p| p == "problem") => {
+                    Some(CourseworkCover {
+                        due,
+                        fields: &cover_fields,
+                    })
+                }
+                _ => None,
+            };
+            let entries: Vec<SheetEntry> = problems
+                .iter()
+                .enumerate()
+                .map(|(n, problem)| SheetEntry {
+                    problem,
+                    parts: course.problem_parts(problem, &edition.parts),
+                    marks: marks.get(n).filter(|_| edition.shows_marks()).cloned(),
+                })
+                .collect();
+            let title = match edition.title {
+                Some(ref title) => format!("{} -- {}", self.title(), title),
+                None => self.title().to_owned(),
+            };
+            let doc = make_edition_sheet(
+                &title,
+                self.intro(),
+                &course.year,
+                &course.course_file.metadata,
+                &entries,
+                &edition,
+                cover.as_ref(),
+            );
+            documents.push((edition.stem(name), doc));
+        }
+
+        Ok(documents)
+    }
+
+    fn build(
+        &self,
+        name: &str,
+        root: &Path,
+        course: &Course,
+        engine: &TexEngine,
+        manifest: &mut BuildManifest,
+    ) -> TeachResult<()> {
+        let config = &course.course_file.config;
+        let problems = course.get_problems(&self.problems())?;
+        for (stem, doc) in self.documents(name, course)? {
+            write_document(
+                &root.join(format!("{}.tex", stem)),
+                doc,
+                &problems,
+                course,
+                manifest,
+            )?;
+        }
+
+        let sources: Vec<DocumentSources> = self
+            .editions(config)
+            .into_iter()
+            .map(|edition| DocumentSources {
+                stem: edition.stem(name),
+                sources: problems
+                    .iter()
+                    .flat_map(|p| course.problem_part_files(p, &edition.parts))
+                    .collect(),
+            })
+            .collect();
+        course
+            .backend()
+            .write_sheet(name, root, &sources, engine, manifest)?;
+        if config.build.html {
+            write_item_pages(name, self, root, course, manifest)?;
         }
         Ok(())
     }
@@ -157,25 +648,125 @@ impl CourseItem {
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Component {
+    #[serde(default)]
+    pub tex: TexConfig,
+
     #[serde(flatten)]
-    pub items: HashMap<String, CourseItem>,
+    pub items: IndexMap<String, CourseItem>,
 }
 
 impl Component {
-    pub fn build(&self, root: &Path, course: &Course) -> TeachResult<()> {
+    pub fn build(
+        &self,
+        component: &str,
+        root: &Path,
+        course: &Course,
+        options: &BuildOptions,
+        manifest: &mut BuildManifest,
+    ) -> TeachResult<()> {
+        let config = &course.course_file.config;
         for (name, item) in self.items.iter() {
+            if !options.selects(component, name) {
+                continue;
+            }
             info!("Creating {}/{}", root.display(), name);
             let path = root.join(name);
-            if !path.exists() {
-                fs::create_dir(&path)?;
-            }
-            item.build(name, &path, course)?;
+            manifest.create_dir(&path)?;
+            let engine = config.engine(&[item.tex(), &self.tex]);
+            item.build(name, &path, course, &engine, manifest)?;
         }
         let mut probs_path = PathBuf::from("..");
         probs_path.push("..");
         probs_path.push(&course.course_file.config.sources.problems);
-        write_component_makefile(root, &probs_path.to_string_lossy(), &["../../include"])?;
+        let items: Vec<&str> = self.items.keys().map(AsRef::as_ref).collect();
+        course.backend().write_component(
+            root,
+            &items,
+            &probs_path.to_string_lossy(),
+            &["../../include", MARKDOWN_DIR],
+            &config.engine(&[&self.tex]),
+            manifest,
+        )?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::course::test_course;
+
+    fn section(marks: Vec<u32>, answer: Option<usize>) -> ExamSection {
+        ExamSection {
+            title: String::from("Section A"),
+            problems: vec![],
+            marks,
+            answer,
+            time: None,
+        }
+    }
+
+    #[test]
+    fn test_section_total_marks() {
+        assert_eq!(section(vec![10, 15], None).total_marks().unwrap(), 25);
+        assert_eq!(
+            section(vec![20, 20, 20], Some(2)).total_marks().unwrap(),
+            40
+        );
+        assert!(section(vec![20, 10, 20], Some(2)).total_marks().is_err());
+        assert!(section(vec![20, 20], Some(3)).total_marks().is_err());
+    }
+
+    #[test]
+    fn test_only_cover_edition_has_cover() {
+        let (_dir, course) = test_course(
+            "[finals.may]\ntitle = \"May exam\"\ntopic = \"Limits\"\nduration = 120\n\
+             [[finals.may.sections]]\ntitle = \"Section A\"\nproblems = [\"limits\"]\nmarks = [10]\n",
+            &[
+                ("problems/limits/problem.tex", "problem"),
+                ("problems/limits/solution.tex", "solution"),
+                ("problems/limits/markscheme.tex", "markscheme"),
+            ],
+        );
+        let (_, item) = course.find_item("finals", "may").unwrap();
+        let maketitle = latex::Element::UserDefined(String::from("\\maketitle"));
+        for (stem, doc) in item.documents("may", &course).unwrap() {
+            let cover = !doc.contains(&maketitle);
+            assert_eq!(cover, stem == "may-cover", "{}", stem);
+        }
+    }
+
+    #[test]
+    fn test_solution_macro_without_marks() {
+        let (_dir, course) = test_course(
+            "[sheets]\nproblem_macro = \"\\\\question\"\n\
+             [solutions]\nproblem_macro = \"\\\\solution\"\n\
+             [assessed.cw1]\ntitle = \"CW 1\"\ntopic = \"Limits\"\n\
+             problems = [\"limits\"]\nmarks = [4]\n",
+            &[
+                ("problems/limits/problem.tex", "problem"),
+                ("problems/limits/solution.tex", "solution"),
+            ],
+        );
+        let (_, item) = course.find_item("assessed", "cw1").unwrap();
+        let entries: HashMap<String, Vec<String>> = item
+            .documents("cw1", &course)
+            .unwrap()
+            .into_iter()
+            .map(|(stem, doc)| {
+                let lines = doc
+                    .iter()
+                    .filter_map(|element| match element {
+                        latex::Element::Environment(_, lines) => Some(lines.clone()),
+                        _ => None,
+                    })
+                    .flatten()
+                    .collect();
+                (stem, lines)
+            })
+            .collect();
+        assert!(entries["cw1-problems"].contains(&String::from("\\question[4]{limits}")));
+        assert!(entries["cw1-solutions"].contains(&String::from("\\solution{limits}")));
+    }
+}
@@ -0,0 +1,143 @@
+use std::cmp::{max, min};
+use std::fmt::Write;
+
+const CONTEXT: usize = 3;
+
+#[derive(Debug, PartialEq)]
+enum Op {
+    Equal(usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+fn diff_ops(a: &[&str], b: &[&str]) -> Vec<Op> {
+    let (n, m) = (a.len(), b.len());
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                max(lcs[i + 1][j], lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if a[i] == b[j] {
+            ops.push(Op::Equal(i));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            ops.push(Op::Delete(i));
+            i += 1;
+        } else {
+            ops.push(Op::Insert(j));
+            j += 1;
+        }
+    }
+    ops.extend((i..n).map(Op::Delete));
+    ops.extend((j..m).map(Op::Insert));
+    ops
+}
+
+fn hunk_range(start: usize, len: usize) -> String {
+    if len == 0 {
+        format!("{},0", start)
+    } else {
+        format!("{},{}", start + 1, len)
+    }
+}
+
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    let ops = diff_ops(&a, &b);
+
+    let changes: Vec<usize> = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, op)| !matches!(op, Op::Equal(_)))
+        .map(|(k, _)| k)
+        .collect();
+
+    let mut out = String::new();
+    if changes.is_empty() {
+        return out;
+    }
+
+    // Position in each file before every op, for hunk headers.
+    let mut positions = Vec::with_capacity(ops.len() + 1);
+    let (mut pa, mut pb) = (0, 0);
+    for op in ops.iter() {
+        positions.push((pa, pb));
+        match op {
+            Op::Equal(_) => {
+                pa += 1;
+                pb += 1;
+            }
+            Op::Delete(_) => pa += 1,
+            Op::Insert(_) => pb += 1,
+        }
+    }
+    positions.push((pa, pb));
+
+    writeln!(out, "--- {}", old_name).unwrap();
+    writeln!(out, "+++ {}", new_name).unwrap();
+
+    let mut k = 0;
+    while k < changes.len() {
+        let start = changes[k].saturating_sub(CONTEXT);
+        let mut last = changes[k];
+        while k + 1 < changes.len() && changes[k + 1] - last <= 2 * CONTEXT {
+            k += 1;
+            last = changes[k];
+        }
+        let end = min(last + CONTEXT + 1, ops.len());
+        k += 1;
+
+        let (sa, sb) = positions[start];
+        let (ea, eb) = positions[end];
+        writeln!(
+            out,
+            "@@ -{} +{} @@",
+            hunk_range(sa, ea - sa),
+            hunk_range(sb, eb - sb)
+        )
+        .unwrap();
+
+        for op in &ops[start..end] {
+            match *op {
+                Op::Equal(i) => writeln!(out, " {}", a[i]).unwrap(),
+                Op::Delete(i) => writeln!(out, "-{}", a[i]).unwrap(),
+                Op::Insert(j) => writeln!(out, "+{}", b[j]).unwrap(),
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\n";
+        let new = "a\nb\nc\nd\nE\nf\ng\nh\ni\n";
+
+        let expected = "--- old\n+++ new\n@@ -2,7 +2,7 @@\n b\n c\n d\n-e\n+E\n f\n g\n h\n";
+
+        assert_eq!(unified_diff(old, new, "old", "new"), expected);
+        assert_eq!(unified_diff(old, old, "old", "new"), "");
+    }
+
+    #[test]
+    fn test_diff_created_file() {
+        let expected = "--- old\n+++ new\n@@ -0,0 +1,2 @@\n+x\n+y\n";
+        assert_eq!(unified_diff("", "x\ny\n", "old", "new"), expected);
+    }
+}
@@ -0,0 +1,162 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::AppConfig;
+use crate::course_items::TexConfig;
+use crate::TeachResult;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BibTool {
+    #[default]
+    Bibtex,
+    Biber,
+}
+
+impl BibTool {
+    pub fn command(self) -> &'static str {
+        match self {
+            BibTool::Bibtex => "bibtex",
+            BibTool::Biber => "biber",
+        }
+    }
+
+    pub fn aux_extension(self) -> &'static str {
+        match self {
+            BibTool::Bibtex => "aux",
+            BibTool::Biber => "bcf",
+        }
+    }
+
+    pub fn citation_marker(self) -> &'static str {
+        match self {
+            BibTool::Bibtex => "\\citation",
+            BibTool::Biber => "bcf:citekey",
+        }
+    }
+
+    pub fn needs_run(self, dir: &Path, jobname: &str) -> TeachResult<bool> {
+        let aux = dir.join(format!("{}.{}", jobname, self.aux_extension()));
+        if !aux.is_file() {
+            return Ok(false);
+        }
+        Ok(String::from_utf8_lossy(&fs::read(aux)?).contains(self.citation_marker()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TexEngine {
+    pub engine: String,
+    pub bib_tool: BibTool,
+    flags: String,
+}
+
+impl TexEngine {
+    pub fn new(engine: &str, flags: &str) -> TexEngine {
+        TexEngine {
+            engine: engine.to_owned(),
+            bib_tool: BibTool::default(),
+            flags: flags.to_owned(),
+        }
+    }
+
+    // Levels are given most specific first, e.g. item, component, course.
+    pub fn resolve(levels: &[&TexConfig]) -> TexEngine {
+        let app_config = AppConfig::get();
+        let engine = levels
+            .iter()
+            .filter_map(|l| l.engine.as_ref())
+            .next()
+            .unwrap_or(&app_config.tex_engine);
+        let flags = levels
+            .iter()
+            .filter_map(|l| l.flags.as_ref())
+            .next()
+            .unwrap_or(&app_config.tex_flags);
+
+        TexEngine::new(engine, flags)
+    }
+
+    pub fn is_tectonic(&self) -> bool {
+        self.engine == "tectonic"
+    }
+
+    pub fn args(&self) -> Vec<String> {
+        let mut args = vec![];
+        for flag in self.flags.split_whitespace() {
+            if !self.is_tectonic() {
+                args.push(flag.to_owned());
+                continue;
+            }
+
+            // tectonic always runs non-interactively and uses GNU style
+            // long options.
+            if flag.starts_with("-interaction")
+                || flag == "-halt-on-error"
+                || flag == "-file-line-error"
+            {
+                continue;
+            } else if flag.starts_with("-synctex") {
+                args.push(String::from("--synctex"));
+            } else if flag == "-shell-escape" {
+                args.push(String::from("-Z"));
+                args.push(String::from("shell-escape"));
+            } else {
+                args.push(flag.to_owned());
+            }
+        }
+
+        // tectonic writes the PDF next to its input, the build rules and
+        // latexmk expect it in the directory they run in.
+        if self.is_tectonic() {
+            args.push(String::from("--outdir"));
+            args.push(String::from("."));
+        }
+        args
+    }
+
+    pub fn flags(&self) -> String {
+        self.args().join(" ")
+    }
+
+    pub fn stdin_args(&self) -> Vec<String> {
+        if self.is_tectonic() {
+            vec![String::from("-")]
+        } else {
+            vec![]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tectonic_flags() {
+        let engine = TexEngine::new("tectonic", "-interaction=nonstopmode -synctex=1");
+        assert_eq!(engine.flags(), "--synctex --outdir .");
+        assert_eq!(engine.stdin_args(), vec!["-"]);
+
+        let engine = TexEngine::new("lualatex", "-interaction=nonstopmode -synctex=1");
+        assert_eq!(engine.flags(), "-interaction=nonstopmode -synctex=1");
+        assert!(engine.stdin_args().is_empty());
+    }
+
+    #[test]
+    fn test_resolve_most_specific() {
+        let item = TexConfig {
+            engine: Some(String::from("lualatex")),
+            flags: None,
+        };
+        let course = TexConfig {
+            engine: Some(String::from("xelatex")),
+            flags: Some(String::from("-shell-escape")),
+        };
+
+        let engine = TexEngine::resolve(&[&item, &TexConfig::default(), &course]);
+        assert_eq!(engine, TexEngine::new("lualatex", "-shell-escape"));
+    }
+}
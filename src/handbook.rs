@@ -0,0 +1,53 @@
+use crate::course_items::CourseItem;
+use crate::Course;
+use crate::TeachResult;
+
+// An item of the course as it appears in the handbook.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HandbookItem {
+    pub component: String,
+    pub title: String,
+    pub intro: String,
+    pub problems: Vec<String>,
+    pub marks: Vec<u32>,
+}
+
+// Every sheet and coursework item, in the order of course.toml. Exams stay
+// out of the students' booklet.
+pub fn build_handbook(course: &Course) -> TeachResult<Vec<HandbookItem>> {
+    let mut items = vec![];
+    for (component, comp) in course.course_file.items.iter() {
+        for item in comp.items.values() {
+            if let CourseItem::Exam(_) = item {
+                continue;
+            }
+            items.push(HandbookItem {
+                component: component.clone(),
+                title: item.title().to_owned(),
+                intro: item.intro().to_owned(),
+                problems: course.get_problems(&item.problems())?,
+                marks: item.marks().to_vec(),
+            });
+        }
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::course::test_course;
+
+    #[test]
+    fn test_exams_left_out() {
+        let (_dir, course) = test_course(
+            "[tutorials.week1]\ntitle = \"Week 1\"\ntopic = \"Limits\"\nproblems = [\"limits\"]\n\
+             [finals.may]\ntitle = \"May exam\"\ntopic = \"Limits\"\nduration = 120\n\
+             [[finals.may.sections]]\ntitle = \"Section A\"\nproblems = [\"limits\"]\nmarks = [10]\n",
+            &[("problems/limits/problem.tex", "problem")],
+        );
+        let items = build_handbook(&course).unwrap();
+        let titles: Vec<&str> = items.iter().map(|item| item.title.as_str()).collect();
+        assert_eq!(titles, vec!["Week 1"]);
+    }
+}
@@ -0,0 +1,743 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::path::Path;
+
+use log::{info, warn};
+
+use crate::course_items::{CourseItem, Edition, Metadata};
+use crate::latexdoc::part_heading;
+use crate::manifest::BuildManifest;
+use crate::Course;
+use crate::TeachResult;
+
+const MATHJAX_URL: &str = "https://cdn.jsdelivr.net/npm/mathjax@3/es5/tex-mml-chtml.js";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MathDelimiters {
+    pub inline: (&'static str, &'static str),
+    pub display: (&'static str, &'static str),
+}
+
+pub const MATHJAX: MathDelimiters = MathDelimiters {
+    inline: ("\\(", "\\)"),
+    display: ("\\[", "\\]"),
+};
+
+// Math environments are turned into the matching inner environment, so
+// they can sit between display delimiters.
+const MATH_ENVIRONMENTS: &[(&str, Option<&str>)] = &[
+    ("equation", None),
+    ("equation*", None),
+    ("displaymath", None),
+    ("multline", None),
+    ("multline*", None),
+    ("align", Some("aligned")),
+    ("align*", Some("aligned")),
+    ("eqnarray", Some("aligned")),
+    ("eqnarray*", Some("aligned")),
+    ("gather", Some("gathered")),
+    ("gather*", Some("gathered")),
+];
+
+// Macros that only affect spacing or layout on paper, with the number of
+// arguments to drop.
+const IGNORED_MACROS: &[(&str, usize)] = &[
+    ("noindent", 0),
+    ("smallskip", 0),
+    ("medskip", 0),
+    ("bigskip", 0),
+    ("centering", 0),
+    ("clearpage", 0),
+    ("newpage", 0),
+    ("label", 1),
+    ("vspace", 1),
+    ("hspace", 1),
+];
+
+const BLOCK_TAGS: &[&str] = &["<ul", "<ol", "<div", "<blockquote", "<section"];
+
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn strip_comments(src: &str) -> String {
+    let mut out = String::with_capacity(src.len());
+    for line in src.lines() {
+        let mut escaped = false;
+        for c in line.chars() {
+            if c == '%' && !escaped {
+                break;
+            }
+            escaped = c == '\\' && !escaped;
+            out.push(c);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn starts_with(chars: &[char], i: usize, pat: &str) -> bool {
+    pat.chars()
+        .enumerate()
+        .all(|(k, p)| chars.get(i + k) == Some(&p))
+}
+
+fn find(chars: &[char], from: usize, pat: &str) -> Option<usize> {
+    (from..chars.len()).find(|&j| starts_with(chars, j, pat) && (j == 0 || chars[j - 1] != '\\'))
+}
+
+fn skip_spaces(chars: &[char], i: &mut usize) {
+    while *i < chars.len() && chars[*i].is_whitespace() {
+        *i += 1;
+    }
+}
+
+fn read_delimited(chars: &[char], i: &mut usize, open: char, close: char) -> Option<String> {
+    let mut j = *i;
+    skip_spaces(chars, &mut j);
+    if chars.get(j) != Some(&open) {
+        return None;
+    }
+
+    let mut depth = 0;
+    let start = j + 1;
+    while j < chars.len() {
+        match chars[j] {
+            '\\' => j += 1,
+            c if c == open => depth += 1,
+            c if c == close => {
+                depth -= 1;
+                if depth == 0 {
+                    *i = j + 1;
+                    return Some(chars[start..j].iter().collect());
+                }
+            }
+            _ => {}
+        }
+        j += 1;
+    }
+    None
+}
+
+fn read_group(chars: &[char], i: &mut usize) -> String {
+    read_delimited(chars, i, '{', '}').unwrap_or_default()
+}
+
+fn read_optional(chars: &[char], i: &mut usize) -> Option<String> {
+    read_delimited(chars, i, '[', ']')
+}
+
+fn read_macro_name(chars: &[char], i: &mut usize) -> String {
+    let mut name = String::new();
+    if *i < chars.len() && !chars[*i].is_ascii_alphabetic() {
+        name.push(chars[*i]);
+        *i += 1;
+        return name;
+    }
+    while *i < chars.len() && chars[*i].is_ascii_alphabetic() {
+        name.push(chars[*i]);
+        *i += 1;
+    }
+    if chars.get(*i) == Some(&'*') {
+        name.push('*');
+        *i += 1;
+    }
+    name
+}
+
+// Body of `\begin{env}` up to its matching `\end{env}`, leaves `i` after it.
+fn read_environment(chars: &[char], i: &mut usize, env: &str) -> String {
+    let begin = format!("\\begin{{{}}}", env);
+    let end = format!("\\end{{{}}}", env);
+    let start = *i;
+    let mut depth = 1;
+    let mut j = *i;
+    while j < chars.len() {
+        if starts_with(chars, j, &begin) {
+            depth += 1;
+        } else if starts_with(chars, j, &end) {
+            depth -= 1;
+            if depth == 0 {
+                *i = j + end.chars().count();
+                return chars[start..j].iter().collect();
+            }
+        }
+        j += 1;
+    }
+    *i = chars.len();
+    chars[start..].iter().collect()
+}
+
+// Split a list body at its top level `\item`s.
+fn split_items(body: &str) -> Vec<(Option<String>, String)> {
+    let chars: Vec<char> = body.chars().collect();
+    let mut items = vec![];
+    let mut current: Option<(Option<String>, usize)> = None;
+    let mut depth = 0i32;
+    let mut i = 0;
+
+    while i < chars.len() {
+        if chars[i] == '\\' {
+            if starts_with(&chars, i, "\\begin{") {
+                depth += 1;
+            } else if starts_with(&chars, i, "\\end{") {
+                depth -= 1;
+            } else if depth == 0
+                && starts_with(&chars, i, "\\item")
+                && !matches!(chars.get(i + 5), Some(c) if c.is_ascii_alphabetic())
+            {
+                if let Some((label, start)) = current.take() {
+                    items.push((label, chars[start..i].iter().collect()));
+                }
+                i += 5;
+                let label = read_optional(&chars, &mut i);
+                current = Some((label, i));
+                continue;
+            }
+            i += 2;
+            continue;
+        }
+        match chars[i] {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            _ => {}
+        }
+        i += 1;
+    }
+
+    if let Some((label, start)) = current {
+        items.push((label, chars[start..].iter().collect()));
+    }
+    items
+}
+
+// The output is also well-formed XML, with no named entities or inline
+// styles, so QTI items can embed it as is.
+pub struct Converter {
+    delimiters: MathDelimiters,
+    unsupported: BTreeSet<String>,
+}
+
+impl Converter {
+    pub fn new(delimiters: MathDelimiters) -> Converter {
+        Converter {
+            delimiters,
+            unsupported: BTreeSet::new(),
+        }
+    }
+
+    pub fn unsupported(&self) -> &BTreeSet<String> {
+        &self.unsupported
+    }
+
+    pub fn convert(&mut self, src: &str) -> String {
+        self.convert_block(&strip_comments(src))
+    }
+
+    fn convert_block(&mut self, src: &str) -> String {
+        let html = self.convert_inline(src);
+        let mut paragraphs = vec![];
+        let mut current = String::new();
+        for line in html.lines() {
+            if line.trim().is_empty() {
+                paragraphs.push(current.clone());
+                current.clear();
+            } else {
+                current.push_str(line);
+                current.push('\n');
+            }
+        }
+        paragraphs.push(current);
+
+        paragraphs
+            .iter()
+            .map(|p| p.trim())
+            .filter(|p| !p.is_empty())
+            .map(|p| {
+                if BLOCK_TAGS.iter().any(|tag| p.starts_with(tag)) {
+                    p.to_owned()
+                } else {
+                    format!("<p>{}</p>", p)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn math(&self, math: &str, display: bool) -> String {
+        let (open, close) = if display {
+            self.delimiters.display
+        } else {
+            self.delimiters.inline
+        };
+        format!("{}{}{}", open, escape(math.trim()), close)
+    }
+
+    fn list(&mut self, body: &str, tag: &str) -> String {
+        let mut html = format!("\n\n<{}>\n", tag);
+        for (label, item) in split_items(body) {
+            let label = match label {
+                Some(label) => format!("<strong>{}</strong> ", self.convert_inline(&label)),
+                None => String::new(),
+            };
+            writeln!(html, "<li>{}{}</li>", label, self.convert_block(&item)).unwrap();
+        }
+        html.push_str(&format!("</{}>\n\n", tag));
+        html
+    }
+
+    fn environment(&mut self, env: &str, body: &str) -> String {
+        if let Some((_, inner)) = MATH_ENVIRONMENTS.iter().find(|(name, _)| *name == env) {
+            let math = match inner {
+                Some(inner) => format!("\\begin{{{0}}}{1}\\end{{{0}}}", inner, body),
+                None => body.to_owned(),
+            };
+            return self.math(&math, true);
+        }
+
+        match env {
+            "itemize" | "description" => self.list(body, "ul"),
+            "enumerate" => self.list(body, "ol"),
+            "center" => format!(
+                "\n\n<div class=\"center\">\n{}\n</div>\n\n",
+                self.convert_block(body)
+            ),
+            "quote" | "quotation" => format!(
+                "\n\n<blockquote>\n{}\n</blockquote>\n\n",
+                self.convert_block(body)
+            ),
+            other => {
+                self.unsupported.insert(format!("{{{}}}", other));
+                format!(
+                    "\n\n<div class=\"{}\">\n{}\n</div>\n\n",
+                    escape(other),
+                    self.convert_block(body)
+                )
+            }
+        }
+    }
+
+    fn command(&mut self, name: &str, chars: &[char], i: &mut usize) -> String {
+        if let Some((_, args)) = IGNORED_MACROS
+            .iter()
+            .find(|(m, _)| *m == name.trim_end_matches('*'))
+        {
+            for _ in 0..*args {
+                read_group(chars, i);
+            }
+            return String::new();
+        }
+
+        match name {
+            "%" | "&" | "$" | "#" | "_" | "{" | "}" => escape(name),
+            " " | "\n" | "," | "quad" | "qquad" => String::from(" "),
+            "\\" | "newline" => {
+                read_optional(chars, i);
+                String::from("<br />")
+            }
+            "par" => String::from("\n\n"),
+            "ldots" | "dots" => String::from("&#8230;"),
+            "LaTeX" => String::from("LaTeX"),
+            "TeX" => String::from("TeX"),
+            "textbf" => format!("<strong>{}</strong>", self.group(chars, i)),
+            "emph" | "textit" => format!("<em>{}</em>", self.group(chars, i)),
+            "texttt" => format!("<code>{}</code>", self.group(chars, i)),
+            "underline" => format!("<span class=\"underline\">{}</span>", self.group(chars, i)),
+            "ref" | "eqref" => format!("({})", escape(&read_group(chars, i))),
+            "cite" => {
+                read_optional(chars, i);
+                format!("[{}]", escape(&read_group(chars, i)))
+            }
+            "url" => {
+                let url = escape(&read_group(chars, i));
+                format!("<a href=\"{0}\">{0}</a>", url)
+            }
+            "href" => {
+                let url = escape(&read_group(chars, i));
+                format!("<a href=\"{}\">{}</a>", url, self.group(chars, i))
+            }
+            "(" => {
+                let end = find(chars, *i, "\\)").unwrap_or(chars.len());
+                let math: String = chars[*i..end].iter().collect();
+                *i = (end + 2).min(chars.len());
+                self.math(&math, false)
+            }
+            "[" => {
+                let end = find(chars, *i, "\\]").unwrap_or(chars.len());
+                let math: String = chars[*i..end].iter().collect();
+                *i = (end + 2).min(chars.len());
+                self.math(&math, true)
+            }
+            "begin" => {
+                let env = read_group(chars, i);
+                let body = read_environment(chars, i, &env);
+                self.environment(&env, &body)
+            }
+            "end" => {
+                read_group(chars, i);
+                String::new()
+            }
+            // Keep the text of anything else so nothing is lost.
+            other => {
+                self.unsupported.insert(format!("\\{}", other));
+                read_optional(chars, i);
+                let mut text = String::new();
+                let mut j = *i;
+                skip_spaces(chars, &mut j);
+                while chars.get(j) == Some(&'{') {
+                    text.push_str(&self.group(chars, i));
+                    j = *i;
+                    skip_spaces(chars, &mut j);
+                }
+                text
+            }
+        }
+    }
+
+    fn group(&mut self, chars: &[char], i: &mut usize) -> String {
+        let group = read_group(chars, i);
+        self.convert_inline(&group)
+    }
+
+    fn convert_inline(&mut self, src: &str) -> String {
+        let chars: Vec<char> = src.chars().collect();
+        let mut out = String::new();
+        let mut i = 0;
+
+        while i < chars.len() {
+            let c = chars[i];
+            match c {
+                '\\' => {
+                    i += 1;
+                    let name = read_macro_name(&chars, &mut i);
+                    let html = self.command(&name, &chars, &mut i);
+                    out.push_str(&html);
+                }
+                '$' => {
+                    let display = chars.get(i + 1) == Some(&'$');
+                    let (start, delim) = if display { (i + 2, "$$") } else { (i + 1, "$") };
+                    let end = find(&chars, start, delim).unwrap_or(chars.len());
+                    let math: String = chars[start..end].iter().collect();
+                    out.push_str(&self.math(&math, display));
+                    i = (end + delim.len()).min(chars.len());
+                }
+                '{' => {
+                    let html = self.group(&chars, &mut i);
+                    out.push_str(&html);
+                }
+                '}' => i += 1,
+                '~' => {
+                    out.push_str("&#160;");
+                    i += 1;
+                }
+                '-' if starts_with(&chars, i, "---") => {
+                    out.push_str("&#8212;");
+                    i += 3;
+                }
+                '-' if starts_with(&chars, i, "--") => {
+                    out.push_str("&#8211;");
+                    i += 2;
+                }
+                '`' if starts_with(&chars, i, "``") => {
+                    out.push_str("&#8220;");
+                    i += 2;
+                }
+                '\'' if starts_with(&chars, i, "''") => {
+                    out.push_str("&#8221;");
+                    i += 2;
+                }
+                _ => {
+                    out.push_str(&escape(&c.to_string()));
+                    i += 1;
+                }
+            }
+        }
+
+        out
+    }
+}
+
+fn page(title: &str, metadata: &Metadata, year: &str, body: &str) -> TeachResult<String> {
+    let mut html = String::new();
+    writeln!(html, "<!DOCTYPE html>")?;
+    writeln!(html, "<html>")?;
+    writeln!(html, "<head>")?;
+    writeln!(html, "<meta charset=\"utf-8\">")?;
+    writeln!(
+        html,
+        "<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">"
+    )?;
+    writeln!(html, "<title>{}</title>", escape(title))?;
+    writeln!(
+        html,
+        "<script id=\"MathJax-script\" async src=\"{}\"></script>",
+        MATHJAX_URL
+    )?;
+    writeln!(
+        html,
+        "<style>.center {{ text-align: center }} .underline {{ text-decoration: underline }}</style>"
+    )?;
+    writeln!(html, "</head>")?;
+    writeln!(html, "<body>")?;
+    writeln!(html, "<header>")?;
+    writeln!(html, "<h1>{}</h1>", escape(title))?;
+    writeln!(
+        html,
+        "<p>{} &middot; {}</p>",
+        escape(&metadata.author),
+        year
+    )?;
+
+    let mut keys: Vec<&String> = metadata.keys().collect();
+    keys.sort();
+    if !keys.is_empty() {
+        writeln!(html, "<dl>")?;
+        for key in keys {
+            writeln!(
+                html,
+                "<dt>{}</dt><dd>{}</dd>",
+                escape(key),
+                escape(&metadata[key])
+            )?;
+        }
+        writeln!(html, "</dl>")?;
+    }
+    writeln!(html, "</header>")?;
+    writeln!(html, "<main>")?;
+    html.push_str(body);
+    writeln!(html, "</main>")?;
+    writeln!(html, "</body>")?;
+    writeln!(html, "</html>")?;
+    Ok(html)
+}
+
+fn problem_body(
+    converter: &mut Converter,
+    course: &Course,
+    item: &CourseItem,
+    problems: &[String],
+    edition: &Edition,
+) -> TeachResult<String> {
+    let marks = item.marks();
+
+    let mut html = String::new();
+    let intro = item.intro();
+    if !intro.is_empty() {
+        writeln!(html, "{}", converter.convert(intro))?;
+    }
+
+    writeln!(html, "<ol>")?;
+    for (n, prob) in problems.iter().enumerate() {
+        writeln!(html, "<li>")?;
+        if let Some(mark) = marks.get(n) {
+            writeln!(html, "<p class=\"marks\">[{} marks]</p>", mark)?;
+        }
+        for part in course.problem_parts(prob, &edition.parts) {
+            writeln!(html, "<section class=\"{}\">", escape(&part))?;
+            if part != "problem" {
+                writeln!(html, "<h2>{}</h2>", escape(&part_heading(&part)))?;
+            }
+            writeln!(
+                html,
+                "{}",
+                converter.convert(&course.problem_latex(prob, &part)?)
+            )?;
+            writeln!(html, "</section>")?;
+        }
+        writeln!(html, "</li>")?;
+    }
+    writeln!(html, "</ol>")?;
+    Ok(html)
+}
+
+// Editions that input problem parts get a page named like their PDF, a
+// cover has nothing to show.
+pub fn has_page(edition: &Edition) -> bool {
+    !edition.parts.is_empty()
+}
+
+pub fn write_item_pages(
+    name: &str,
+    item: &CourseItem,
+    root: &Path,
+    course: &Course,
+    manifest: &mut BuildManifest,
+) -> TeachResult<()> {
+    info!("Creating HTML pages for {}", name);
+    let metadata = &course.course_file.metadata;
+    let problems = course.get_problems(&item.problems())?;
+    let mut converter = Converter::new(MATHJAX);
+
+    for edition in item.editions(&course.course_file.config) {
+        if !has_page(&edition) {
+            continue;
+        }
+        let title = match edition.title {
+            Some(ref title) => format!("{} -- {}", item.title(), title),
+            None => item.title().to_owned(),
+        };
+        let page = page(
+            &title,
+            metadata,
+            &course.year,
+            &problem_body(&mut converter, course, item, &problems, &edition)?,
+        )?;
+        manifest.write_file(&root.join(format!("{}.html", edition.stem(name))), &page)?;
+    }
+
+    if !converter.unsupported().is_empty() {
+        let names: Vec<&str> = converter.unsupported().iter().map(AsRef::as_ref).collect();
+        warn!(
+            "{}: unsupported LaTeX left as text in HTML: {}",
+            name,
+            names.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+pub fn write_index(path: &Path, course: &Course, manifest: &mut BuildManifest) -> TeachResult<()> {
+    let mut body = String::new();
+    for (component, comp) in &course.course_file.items {
+        writeln!(body, "<section>")?;
+        writeln!(body, "<h2>{}</h2>", escape(component))?;
+        writeln!(body, "<ul>")?;
+
+        for (name, item) in &comp.items {
+            let link = |edition: &Edition, text: &str| {
+                format!(
+                    "<a href=\"{}/{}/{}.html\">{}</a>",
+                    escape(component),
+                    escape(name),
+                    escape(&edition.stem(name)),
+                    escape(text)
+                )
+            };
+            // The first page is linked from the title, the others by
+            // edition name.
+            let editions: Vec<_> = item
+                .editions(&course.course_file.config)
+                .into_iter()
+                .filter(|edition| has_page(edition))
+                .collect();
+            let (first, rest) = match editions.split_first() {
+                Some(split) => split,
+                None => continue,
+            };
+            write!(body, "<li>{}", link(first, item.title()))?;
+            if !rest.is_empty() {
+                let others: Vec<String> = rest.iter().map(|e| link(e, &e.name)).collect();
+                write!(body, " ({})", others.join(", "))?;
+            }
+            writeln!(body, "</li>")?;
+        }
+
+        writeln!(body, "</ul>")?;
+        writeln!(body, "</section>")?;
+    }
+
+    let index = page(
+        "Course index",
+        &course.course_file.metadata,
+        &course.year,
+        &body,
+    )?;
+    manifest.write_file(&path.join("index.html"), &index)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::course::test_course;
+
+    #[test]
+    fn test_convert_text_and_math() {
+        let mut converter = Converter::new(MATHJAX);
+        let html = converter.convert(
+            "Show that $x < 1$ \\emph{always}. % a comment\n\n\
+             \\begin{align*}a &= b\\end{align*}",
+        );
+        assert_eq!(
+            html,
+            "<p>Show that \\(x &lt; 1\\) <em>always</em>.</p>\n\
+             <p>\\[\\begin{aligned}a &amp;= b\\end{aligned}\\]</p>"
+        );
+        assert!(converter.unsupported().is_empty());
+    }
+
+    #[test]
+    fn test_lists_and_unsupported() {
+        let mut converter = Converter::new(MATHJAX);
+        let html = converter
+            .convert("\\begin{enumerate}\\item First \\item[(b)] \\foo{Second}\\end{enumerate}");
+        assert_eq!(
+            html,
+            "<ol>\n<li><p>First</p></li>\n<li><strong>(b)</strong> <p>Second</p></li>\n</ol>"
+        );
+        assert!(converter.unsupported().contains("\\foo"));
+    }
+
+    #[test]
+    fn test_pages_follow_editions() {
+        let (_dir, course) = test_course(
+            "[build]\nhtml = true\n\
+             [[editions]]\nname = \"problems\"\nparts = [\"problem\"]\n\
+             [[editions]]\nname = \"hints\"\ntitle = \"Hints\"\nparts = [\"problem\", \"hints\"]\n\
+             [tutorials.week1]\ntitle = \"Week 1\"\ntopic = \"Limits\"\n\
+             problems = [\"limits\"]\n",
+            &[
+                ("problems/limits/problem.tex", "problem"),
+                ("problems/limits/solution.tex", "solution"),
+                ("problems/limits/hints.tex", "hints"),
+            ],
+        );
+        course.build(&Default::default()).unwrap();
+
+        let root = &course.path;
+        let item = root.join(&course.year).join("tutorials/week1");
+        let hints = std::fs::read_to_string(item.join("week1-hints.html")).unwrap();
+        assert!(hints.contains("<title>Week 1 -- Hints</title>"));
+        assert!(hints.contains("<section class=\"hints\">\n<h2>Hints</h2>\n<p>hints</p>"));
+        assert!(item.join("week1-problems.html").is_file());
+        assert!(!item.join("week1-solutions.html").exists());
+
+        let index = std::fs::read_to_string(root.join(&course.year).join("index.html")).unwrap();
+        assert!(index.contains(
+            "<li><a href=\"tutorials/week1/week1-problems.html\">Week 1</a> \
+             (<a href=\"tutorials/week1/week1-hints.html\">hints</a>)</li>"
+        ));
+    }
+
+    #[test]
+    fn test_index_follows_course_order() {
+        let (_dir, course) = test_course(
+            "[build]\nhtml = true\n\
+             [tutorials.week2]\ntitle = \"Week 2\"\ntopic = \"Series\"\nproblems = [\"limits\"]\n\
+             [tutorials.week1]\ntitle = \"Week 1\"\ntopic = \"Limits\"\nproblems = [\"limits\"]\n\
+             [assignments.one]\ntitle = \"One\"\ntopic = \"Limits\"\nproblems = [\"limits\"]\n",
+            &[
+                ("problems/limits/problem.tex", "problem"),
+                ("problems/limits/solution.tex", "solution"),
+            ],
+        );
+        course.build(&Default::default()).unwrap();
+
+        let index =
+            std::fs::read_to_string(course.path.join(&course.year).join("index.html")).unwrap();
+        let at = |text: &str| index.find(text).unwrap();
+        assert!(at("Week 2") < at("Week 1"));
+        assert!(at("<h2>tutorials</h2>") < at("<h2>assignments</h2>"));
+    }
+}
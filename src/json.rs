@@ -0,0 +1,235 @@
+use std::fs;
+use std::path::Path;
+
+use indexmap::IndexMap;
+use log::info;
+use serde::Serialize;
+
+use crate::course_items::{Config, CourseItem, Metadata, SheetConfig, TexConfig};
+use crate::html::has_page;
+use crate::Course;
+use crate::TeachResult;
+
+// Bump when a field is removed or changes meaning, and update the schema.
+pub const SCHEMA_VERSION: u32 = 1;
+
+pub const SCHEMA: &str = include_str!("../schema/course-v1.json");
+
+#[derive(Serialize, Debug)]
+pub struct ProblemExport {
+    pub name: String,
+    pub marks: Option<u32>,
+    pub problem: String,
+    pub solution: String,
+    pub markscheme: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DocumentExport {
+    pub tex: String,
+    pub pdf: String,
+    pub html: Option<String>,
+    pub sheet_config: SheetConfig,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ItemExport<'a> {
+    pub kind: &'static str,
+    pub title: &'a str,
+    pub topic: &'a str,
+    pub intro: Option<&'a str>,
+    pub due: Option<&'a str>,
+    pub duration: Option<u32>,
+    pub total_marks: Option<u32>,
+    pub tex: &'a TexConfig,
+    pub problems: Vec<ProblemExport>,
+    pub documents: IndexMap<String, DocumentExport>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ComponentExport<'a> {
+    pub tex: &'a TexConfig,
+    pub items: IndexMap<&'a str, ItemExport<'a>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CourseExport<'a> {
+    pub schema_version: u32,
+    pub year: &'a str,
+    pub metadata: &'a Metadata,
+    pub config: &'a Config,
+    pub components: IndexMap<&'a str, ComponentExport<'a>>,
+}
+
+// Sources and HTML pages are written to the item directory, every backend
+// compiles from the component directory, so the PDFs end up there.
+fn export_item<'a>(
+    course: &'a Course,
+    comp_dir: &str,
+    name: &str,
+    item: &'a CourseItem,
+) -> TeachResult<ItemExport<'a>> {
+    let config = &course.course_file.config;
+    let problems_dir = &config.sources.problems;
+    let marks = item.marks();
+
+    let problems = course
+        .get_problems(&item.problems())?
+        .into_iter()
+        .enumerate()
+        .map(|(n, prob)| {
+            let files = course.problem_files(&prob);
+            ProblemExport {
+                marks: marks.get(n).cloned(),
+                problem: format!("{}/{}", problems_dir, files.problem),
+                solution: format!("{}/{}", problems_dir, files.solution),
+                markscheme: files
+                    .markscheme
+                    .map(|file| format!("{}/{}", problems_dir, file)),
+                name: prob,
+            }
+        })
+        .collect();
+
+    let dir = format!("{}/{}", comp_dir, name);
+    let documents = item
+        .editions(config)
+        .into_iter()
+        .map(|edition| {
+            let stem = edition.stem(name);
+            let doc = DocumentExport {
+                tex: format!("{}/{}.tex", dir, stem),
+                pdf: format!("{}/{}.pdf", comp_dir, stem),
+                html: if config.build.html && has_page(&edition) {
+                    Some(format!("{}/{}.html", dir, stem))
+                } else {
+                    None
+                },
+                sheet_config: edition.sheet_config.clone(),
+            };
+            (edition.name.clone(), doc)
+        })
+        .collect();
+
+    let (kind, intro, duration, total_marks) = match item {
+        CourseItem::Sheet(info) => ("sheet", &info.intro, None, None),
+        CourseItem::Coursework(info) => (
+            "coursework",
+            &info.intro,
+            None,
+            Some(info.marks.iter().sum()),
+        ),
+        CourseItem::Exam(info) => {
+            let mut total = 0;
+            for section in info.sections.iter() {
+                total += section.total_marks()?;
+            }
+            ("exam", &info.instructions, Some(info.duration), Some(total))
+        }
+    };
+
+    Ok(ItemExport {
+        kind,
+        title: item.title(),
+        topic: item.topic(),
+        intro: intro.as_ref().map(String::as_str),
+        due: match item {
+            CourseItem::Coursework(info) => info.due.as_deref(),
+            _ => None,
+        },
+        duration,
+        total_marks,
+        tex: item.tex(),
+        problems,
+        documents,
+    })
+}
+
+// Components and items keep the order of course.toml, generated paths
+// are relative to the course directory.
+pub fn course_export(course: &Course) -> TeachResult<CourseExport<'_>> {
+    let mut components = IndexMap::new();
+    for (component, comp) in course.course_file.items.iter() {
+        let mut items = IndexMap::new();
+        let comp_dir = format!("{}/{}", course.year, component);
+        for (name, item) in comp.items.iter() {
+            items.insert(name.as_str(), export_item(course, &comp_dir, name, item)?);
+        }
+        components.insert(
+            component.as_str(),
+            ComponentExport {
+                tex: &comp.tex,
+                items,
+            },
+        );
+    }
+
+    Ok(CourseExport {
+        schema_version: SCHEMA_VERSION,
+        year: &course.year,
+        metadata: &course.course_file.metadata,
+        config: &course.course_file.config,
+        components,
+    })
+}
+
+pub fn export_json(course: &Course, output: Option<&Path>) -> TeachResult<()> {
+    let json = serde_json::to_string_pretty(&course_export(course)?)?;
+    match output {
+        Some(path) => {
+            fs::write(path, json + "\n")?;
+            info!("Exported course structure to {}", path.display());
+        }
+        None => println!("{}", json),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::course::{test_course, BuildOptions};
+
+    #[test]
+    fn test_paths_match_build() {
+        let (_dir, course) = test_course(
+            "[build]\nhtml = true\n\
+             [tutorials.week1]\ntitle = \"Week 1\"\ntopic = \"Limits\"\n\
+             problems = [\"limits\"]\n",
+            &[
+                ("problems/limits/problem.tex", "x\n"),
+                ("problems/limits/solution.tex", "y\n"),
+            ],
+        );
+        course.build(&BuildOptions::default()).unwrap();
+        let root = &course.path;
+        let export = course_export(&course).unwrap();
+
+        let comp_dir = root.join(&course.year).join("tutorials");
+        let makefile = fs::read_to_string(comp_dir.join("week1/week1.mk")).unwrap();
+        let item = &export.components["tutorials"].items["week1"];
+        assert!(!item.documents.is_empty());
+        for doc in item.documents.values() {
+            assert!(root.join(&doc.tex).is_file(), "{} not built", doc.tex);
+            if let Some(ref html) = doc.html {
+                assert!(root.join(html).is_file(), "{} not built", html);
+            }
+
+            // make runs in the component directory and writes the PDF
+            // named by the rule there.
+            let pdf = root.join(&doc.pdf);
+            assert_eq!(pdf.parent(), Some(comp_dir.as_path()));
+            let target = pdf.file_name().unwrap().to_string_lossy();
+            assert!(makefile.contains(&format!("\n{}: ", target)));
+        }
+    }
+
+    #[test]
+    fn test_schema_version() {
+        let schema: serde_json::Value = serde_json::from_str(SCHEMA).unwrap();
+        assert_eq!(
+            schema["properties"]["schema_version"]["const"],
+            serde_json::json!(SCHEMA_VERSION)
+        );
+    }
+}
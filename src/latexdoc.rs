@@ -2,7 +2,11 @@ use std::collections::HashMap;
 
 use latex::{Document, DocumentClass, Element, Paragraph, PreambleElement};
 
-use crate::course_items::{Metadata, SheetConfig};
+use crate::catalogue::Catalogue;
+use crate::course_items::{BibConfig, Edition, Metadata, SheetConfig};
+use crate::engine::BibTool;
+use crate::handbook::HandbookItem;
+use crate::problem::MARKSCHEME;
 
 fn make_basic_doc(doc_class: &str, title: &str, date: &str, metadata: &Metadata) -> Document {
     let document_class = match doc_class {
@@ -28,9 +32,9 @@ fn make_basic_doc(doc_class: &str, title: &str, date: &str, metadata: &Metadata)
     doc
 }
 
-pub fn make_sheet(
+// The document class and preamble of a sheet, without any body.
+fn make_sheet_doc(
     title: &str,
-    intro: &str,
     date: &str,
     metadata: &Metadata,
     sheet_config: &SheetConfig,
@@ -47,89 +51,702 @@ pub fn make_sheet(
             .push(PreambleElement::UserDefined(include_preamble.to_owned()));
     }
 
-    doc.push(Element::UserDefined("\\maketitle".to_owned()));
-
-    doc.push(Element::Para(Paragraph::from(intro)));
-
     doc
 }
 
-pub fn make_problem_sheet<S: AsRef<str>>(
+// Documents typeset with one config that also input solutions need the
+// solution preamble as well, unless it is the same.
+fn add_solution_preamble(
+    doc: &mut Document,
+    config: &SheetConfig,
+    solution_config: Option<&SheetConfig>,
+) {
+    let include_preamble = match solution_config.and_then(|sol| sol.include_preamble.as_ref()) {
+        Some(include_preamble) => include_preamble,
+        None => return,
+    };
+    if config.include_preamble.as_ref() != Some(include_preamble) {
+        doc.preamble
+            .push(PreambleElement::UserDefined(include_preamble.to_owned()));
+    }
+}
+
+pub fn make_sheet(
     title: &str,
     intro: &str,
     date: &str,
     metadata: &Metadata,
-    problems: &[S],
     sheet_config: &SheetConfig,
 ) -> Document {
-    let mut doc = make_sheet(title, intro, date, metadata, sheet_config);
+    let mut doc = make_sheet_doc(title, date, metadata, sheet_config);
 
-    let problem_macro = match sheet_config.problem_macro.as_ref() {
-        Some(ref mac) => mac,
-        None => "\\item\\input",
-    };
+    doc.push(Element::UserDefined("\\maketitle".to_owned()));
+
+    doc.push(Element::Para(Paragraph::from(intro)));
 
-    if !problems.is_empty() {
-        doc.push(Element::Environment(
-            "enumerate".to_owned(),
-            problems
-                .iter()
-                .map(|item| format!("{}{{{}}}", problem_macro, item.as_ref()))
-                .collect(),
-        ));
-    }
     doc
 }
 
-pub fn make_coursework_sheet<S: AsRef<str>>(
+pub struct SheetEntry<'a> {
+    pub problem: &'a str,
+    pub parts: Vec<String>,
+    pub marks: Option<u32>,
+}
+
+pub fn part_heading(part: &str) -> String {
+    if part == MARKSCHEME {
+        return String::from("Mark scheme");
+    }
+    let mut chars = part.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+// A configured macro is given the problem name, and the marks as an
+// optional argument. Otherwise each part is input from the problem
+// directory, later parts under a heading.
+fn edition_entry(entry: &SheetEntry, sheet_config: &SheetConfig) -> String {
+    if let Some(ref mac) = sheet_config.problem_macro {
+        return match entry.marks {
+            Some(marks) => format!("{}[{}]{{{}}}", mac, marks, entry.problem),
+            None => format!("{}{{{}}}", mac, entry.problem),
+        };
+    }
+
+    let mut text = String::from("\\item");
+    if let Some(marks) = entry.marks {
+        text.push_str(&format!(" \\textbf{{[{} marks]}}\\par", marks));
+    }
+    for (i, part) in entry.parts.iter().enumerate() {
+        if i > 0 {
+            text.push_str(&format!(
+                "\n\\par\\medskip\\textbf{{{}}}\\par",
+                part_heading(part)
+            ));
+        }
+        text.push_str(&format!("\n\\input{{{}/{}.tex}}", entry.problem, part));
+    }
+    text
+}
+
+// Marks are added up in a table at the end, with a blank column for the
+// marker.
+fn marks_table(entries: &[SheetEntry]) -> String {
+    let mut table = String::from(
+        "\\section*{Marks}\n\\begin{tabular}{lrr}\nProblem & Available & Awarded \\\\\n\\hline\n",
+    );
+    let mut total = 0;
+    for (n, entry) in entries.iter().enumerate() {
+        let marks = entry.marks.unwrap_or(0);
+        total += marks;
+        table.push_str(&format!("{} & {} & \\\\\n", n + 1, marks));
+    }
+    table.push_str(&format!(
+        "\\hline\nTotal & {} & \\\\\n\\end{{tabular}}",
+        total
+    ));
+    table
+}
+
+pub struct CourseworkCover<'a> {
+    pub due: Option<&'a str>,
+    pub fields: &'a [String],
+}
+
+// One column per problem, so students can see what each is worth and
+// markers can fill in the bottom row.
+fn marks_grid(entries: &[SheetEntry]) -> String {
+    let total: u32 = entries.iter().filter_map(|entry| entry.marks).sum();
+    let mut numbers = String::from("Question");
+    let mut available = String::from("Available");
+    for (n, entry) in entries.iter().enumerate() {
+        numbers.push_str(&format!(" & {}", n + 1));
+        available.push_str(&format!(" & {}", entry.marks.unwrap_or(0)));
+    }
+
+    format!(
+        "\\begin{{center}}\n\\begin{{tabular}}{{|l|{}c|}}\n\\hline\n\
+         {} & Total \\\\\n\\hline\n{} & {} \\\\\n\\hline\n\
+         Awarded{} & \\\\[1.5em]\n\\hline\n\\end{{tabular}}\n\\end{{center}}",
+        "c|".repeat(entries.len()),
+        numbers,
+        available,
+        total,
+        " &".repeat(entries.len())
+    )
+}
+
+// The title is LaTeX, as it is in `\title`.
+fn coursework_cover(title: &str, cover: &CourseworkCover, entries: &[SheetEntry]) -> Vec<Element> {
+    let mut heading = format!(
+        "\\thispagestyle{{empty}}\n\\begin{{center}}\n{{\\Large\\textbf{{{}}}}}",
+        title
+    );
+    if let Some(due) = cover.due {
+        heading.push_str(&format!("\\par\\medskip\nDue: {}", escape(due)));
+    }
+    heading.push_str("\n\\end{center}\n\\vspace{2em}");
+
+    let mut fields = String::from("\\noindent\\begin{tabular}{@{}lp{8cm}@{}}\n");
+    for field in cover.fields {
+        fields.push_str(&format!("{}: & \\hrulefill \\\\[1.5em]\n", escape(field)));
+    }
+    fields.push_str("\\end{tabular}\n\\vspace{2em}");
+
+    vec![
+        Element::UserDefined(heading),
+        Element::UserDefined(fields),
+        Element::UserDefined(marks_grid(entries)),
+        Element::ClearPage,
+    ]
+}
+
+// With a cover the title is on the cover page rather than `\maketitle`.
+pub fn make_edition_sheet(
     title: &str,
     intro: &str,
     date: &str,
     metadata: &Metadata,
-    problems: &[S],
-    marks: &[u32],
-    sheet_config: &SheetConfig,
+    entries: &[SheetEntry],
+    edition: &Edition,
+    cover: Option<&CourseworkCover>,
 ) -> Document {
-    let mut doc = make_sheet(title, intro, date, metadata, sheet_config);
-
-    let problem_macro = match sheet_config.problem_macro.as_ref() {
-        Some(ref mac) => mac,
-        None => "\\item\\input",
+    let mut doc = match cover {
+        Some(cover) => {
+            let mut doc = make_sheet_doc(title, date, metadata, &edition.sheet_config);
+            for element in coursework_cover(title, cover, entries) {
+                doc.push(element);
+            }
+            doc.push(Element::Para(Paragraph::from(intro)));
+            doc
+        }
+        None => make_sheet(title, intro, date, metadata, &edition.sheet_config),
     };
 
-    if !problems.is_empty() {
+    if !entries.is_empty() {
         doc.push(Element::Environment(
             "enumerate".to_owned(),
-            problems
+            entries
                 .iter()
-                .zip(marks.iter())
-                .map(|(item, mark)| format!("{}[{}]{{{}}}", problem_macro, mark, item.as_ref()))
+                .map(|entry| edition_entry(entry, &edition.sheet_config))
                 .collect(),
         ));
     }
+
+    if edition.marks {
+        doc.push(Element::UserDefined(marks_table(entries)));
+    }
     doc
 }
 
-pub fn make_preview_sheet(problem: &str, sheet_config: &SheetConfig) -> Document {
+// For images in Markdown problems.
+pub fn add_graphicx(doc: &mut Document) {
+    doc.preamble.push(PreambleElement::UserDefined(String::from(
+        "\\usepackage{graphicx}",
+    )));
+}
+
+pub fn add_bibliography(doc: &mut Document, bibliography: &BibConfig, path: &str) {
+    match bibliography.tool {
+        BibTool::Bibtex => {
+            let style = bibliography.style.as_ref().map_or("plain", String::as_str);
+            doc.push(Element::UserDefined(format!(
+                "\\bibliographystyle{{{}}}",
+                style
+            )));
+            doc.push(Element::UserDefined(format!(
+                "\\bibliography{{{}}}",
+                path.trim_end_matches(".bib")
+            )));
+        }
+        BibTool::Biber => {
+            let style = bibliography
+                .style
+                .as_ref()
+                .map_or("numeric", String::as_str);
+            doc.preamble.push(PreambleElement::UserDefined(format!(
+                "\\usepackage[backend=biber,style={}]{{biblatex}}",
+                style
+            )));
+            doc.preamble.push(PreambleElement::UserDefined(format!(
+                "\\addbibresource{{{}}}",
+                path
+            )));
+            doc.push(Element::UserDefined("\\printbibliography".to_owned()));
+        }
+    }
+}
+
+// The macros default to inputting the part straight from the problem
+// directory, a configured macro is given the problem name like on sheets.
+fn preview_entry(problem: &str, part: &str, sheet_config: &SheetConfig, item: bool) -> String {
+    match sheet_config.problem_macro {
+        Some(ref mac) => format!("{}{{{}}}", mac, problem),
+        None if item => format!("\\item\\input{{{}/{}.tex}}", problem, part),
+        None => format!("\\input{{{}/{}.tex}}", problem, part),
+    }
+}
+
+// Either config may be left out to preview only problems or only
+// solutions. With both, the document follows the problem config and the
+// solution preamble is added to it.
+pub fn make_preview_sheet<S: AsRef<str>>(
+    problems: &[S],
+    problem_config: Option<&SheetConfig>,
+    solution_config: Option<&SheetConfig>,
+) -> Document {
     let md = Metadata {
         author: "preview".to_owned(),
         other: HashMap::new(),
     };
     let date = String::new();
-    let title = format!("{} Preview", problem);
-    let mut doc = make_sheet(&title, "", &date, &md, sheet_config);
+    let title = match problems {
+        [problem] => format!("{} Preview", problem.as_ref()),
+        _ => format!("Preview of {} problems", problems.len()),
+    };
+    let default_config = SheetConfig::default();
+    let doc_config = problem_config
+        .or(solution_config)
+        .unwrap_or(&default_config);
+    let mut doc = make_sheet(&title, "", &date, &md, doc_config);
 
-    let prob_path = format!("{}/problem.tex", problem);
-    let sol_path = format!("{}/solution.tex", problem);
+    if let Some(prob) = problem_config {
+        add_solution_preamble(&mut doc, prob, solution_config);
+    }
 
-    doc.push(
-        Element::Environment(
-            "enumerate".to_owned(),
-            vec![
-                format!("\\item\\input{{{}}}\n\\vspace*{{2em}}\\hrule\n\n\\textbf{{Solution}}\\par\n\\input{{{}}}", prob_path, sol_path)
-            ]
-        )
-    );
+    for problem in problems {
+        let problem = problem.as_ref();
+        let entry = match (problem_config, solution_config) {
+            (Some(prob), Some(sol)) => format!(
+                "{}\n\\vspace*{{2em}}\\hrule\n\n\\textbf{{Solution}}\\par\n{}",
+                preview_entry(problem, "problem", prob, true),
+                preview_entry(problem, "solution", sol, false)
+            ),
+            (Some(prob), None) => preview_entry(problem, "problem", prob, true),
+            (None, Some(sol)) => preview_entry(problem, "solution", sol, true),
+            (None, None) => continue,
+        };
+
+        doc.push(Element::UserDefined(format!(
+            "\\section*{{{}}}",
+            escape(problem)
+        )));
+        doc.push(Element::Environment("enumerate".to_owned(), vec![entry]));
+    }
 
     doc
 }
+
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\textbackslash{}"),
+            '~' => out.push_str("\\textasciitilde{}"),
+            '^' => out.push_str("\\textasciicircum{}"),
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                out.push('\\');
+                out.push(c);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+// Bookmarks for the sections of the catalogue and handbook. hyperref has
+// to come after the other packages, so it is added once the preamble is
+// complete.
+pub fn add_hyperref(doc: &mut Document) {
+    doc.preamble.push(PreambleElement::UserDefined(
+        "\\usepackage[bookmarks=true,bookmarksnumbered=true]{hyperref}".to_owned(),
+    ));
+}
+
+pub fn make_catalogue(
+    catalogue: &Catalogue,
+    metadata: &Metadata,
+    date: &str,
+    sheet_config: &SheetConfig,
+    solution_config: Option<&SheetConfig>,
+) -> Document {
+    let mut doc = make_sheet("Problem Catalogue", "", date, metadata, sheet_config);
+    add_solution_preamble(&mut doc, sheet_config, solution_config);
+
+    doc.push(Element::TableOfContents);
+    doc.push(Element::ClearPage);
+
+    for (group, entries) in catalogue.iter() {
+        doc.push(Element::UserDefined(format!(
+            "\\section{{{}}}",
+            escape(group)
+        )));
+
+        for entry in entries {
+            doc.push(Element::UserDefined(format!(
+                "\\subsection{{{}}}",
+                escape(&entry.name)
+            )));
+
+            let used_in = if entry.used_in.is_empty() {
+                String::from("not used")
+            } else {
+                escape(&entry.used_in.join(", "))
+            };
+            let mut details = vec![format!("\\item[Used in] {}", used_in)];
+            for (key, value) in entry.meta.iter() {
+                details.push(format!("\\item[{}] {}", escape(key), escape(value)));
+            }
+            doc.push(Element::Environment("description".to_owned(), details));
+
+            let problem = preview_entry(&entry.name, "problem", sheet_config, true);
+            let body = match solution_config {
+                Some(sol) => format!(
+                    "{}\n\\vspace*{{1em}}\n\n\\textbf{{Solution}}\\par\n{}",
+                    problem,
+                    preview_entry(&entry.name, "solution", sol, false)
+                ),
+                None => problem,
+            };
+            doc.push(Element::Environment("enumerate".to_owned(), vec![body]));
+        }
+    }
+
+    doc
+}
+
+// The sectioning commands for items and for the solutions of each item.
+// Classes without \chapter, like article, move everything down a level.
+fn handbook_levels(document_class: &str) -> (&'static str, &'static str) {
+    match document_class {
+        "report" | "book" | "memoir" | "scrreprt" | "scrbook" => ("chapter", "section"),
+        _ => ("section", "subsection"),
+    }
+}
+
+// Problems are numbered continuously through the handbook, so each list
+// starts where the previous one stopped. Solutions follow in an appendix
+// with the same numbers.
+pub fn make_handbook(
+    items: &[HandbookItem],
+    metadata: &Metadata,
+    date: &str,
+    handbook_config: &SheetConfig,
+    solution_config: Option<&SheetConfig>,
+) -> Document {
+    let config = SheetConfig {
+        document_class: handbook_config
+            .document_class
+            .clone()
+            .or_else(|| Some(String::from("report"))),
+        ..handbook_config.clone()
+    };
+    let (item_level, solution_level) =
+        handbook_levels(config.document_class.as_deref().unwrap_or_default());
+    let mut doc = make_sheet("Course Handbook", "", date, metadata, &config);
+    add_solution_preamble(&mut doc, &config, solution_config);
+
+    doc.push(Element::TableOfContents);
+    doc.push(Element::ClearPage);
+
+    // Only the problems carry marks, as in the coursework sheets.
+    let numbered = |item: &HandbookItem, start: usize, parts: &[&str], cfg: &SheetConfig| {
+        let mut lines = vec![format!("\\setcounter{{enumi}}{{{}}}", start)];
+        for (n, problem) in item.problems.iter().enumerate() {
+            let entry = SheetEntry {
+                problem,
+                parts: parts.iter().map(|p| String::from(*p)).collect(),
+                marks: item
+                    .marks
+                    .get(n)
+                    .filter(|_| parts.contains(&"problem"))
+                    .cloned(),
+            };
+            lines.push(edition_entry(&entry, cfg));
+        }
+        Element::Environment("enumerate".to_owned(), lines)
+    };
+
+    let mut component = None;
+    let mut start = 0;
+    for item in items {
+        if component != Some(&item.component) {
+            doc.push(Element::UserDefined(format!(
+                "\\part{{{}}}",
+                escape(&item.component)
+            )));
+            component = Some(&item.component);
+        }
+        // Titles are LaTeX, as on the item's own sheets.
+        doc.push(Element::UserDefined(format!(
+            "\\{}{{{}}}",
+            item_level, item.title
+        )));
+        if !item.intro.is_empty() {
+            doc.push(Element::Para(Paragraph::from(item.intro.as_str())));
+        }
+        if !item.problems.is_empty() {
+            doc.push(numbered(item, start, &["problem"], &config));
+        }
+        start += item.problems.len();
+    }
+
+    if let Some(sol) = solution_config {
+        doc.push(Element::UserDefined("\\appendix".to_owned()));
+        doc.push(Element::UserDefined(format!(
+            "\\{}{{Solutions}}",
+            item_level
+        )));
+        let mut start = 0;
+        for item in items {
+            if !item.problems.is_empty() {
+                doc.push(Element::UserDefined(format!(
+                    "\\{}{{{}}}",
+                    solution_level, item.title
+                )));
+                doc.push(numbered(item, start, &["solution"], sol));
+            }
+            start += item.problems.len();
+        }
+    }
+
+    doc
+}
+
+pub struct PaperSection<'a> {
+    pub title: &'a str,
+    pub answer: Option<usize>,
+    pub time: Option<u32>,
+    pub marks: u32,
+    pub entries: Vec<SheetEntry<'a>>,
+}
+
+pub struct ExamPaper<'a> {
+    pub title: String,
+    pub duration: u32,
+    pub instructions: &'a str,
+    pub sections: Vec<PaperSection<'a>>,
+}
+
+fn format_minutes(minutes: u32) -> String {
+    let plural = |n: u32, unit: &str| format!("{} {}{}", n, unit, if n == 1 { "" } else { "s" });
+    match (minutes / 60, minutes % 60) {
+        (0, m) => plural(m, "minute"),
+        (h, 0) => plural(h, "hour"),
+        (h, m) => format!("{} {}", plural(h, "hour"), plural(m, "minute")),
+    }
+}
+
+fn answer_rule(section: &PaperSection) -> String {
+    match section.answer {
+        Some(n) if n < section.entries.len() => {
+            format!("Answer {} of {}", n, section.entries.len())
+        }
+        _ => String::from("Answer all"),
+    }
+}
+
+// The title is LaTeX, as it is in `\title`.
+fn exam_cover(paper: &ExamPaper) -> Vec<Element> {
+    let total: u32 = paper.sections.iter().map(|s| s.marks).sum();
+    let mut cover = vec![Element::UserDefined(format!(
+        "\\begin{{center}}\n{{\\Large\\textbf{{{}}}}}\\par\\medskip\n\
+         Time allowed: {}\\par\nTotal marks: {}\n\\end{{center}}",
+        paper.title,
+        format_minutes(paper.duration),
+        total
+    ))];
+
+    if !paper.instructions.is_empty() {
+        cover.push(Element::UserDefined(String::from(
+            "\\section*{Instructions}",
+        )));
+        cover.push(Element::Para(Paragraph::from(paper.instructions)));
+    }
+
+    let mut table =
+        String::from("\\begin{tabular}{lllr}\nSection & & Time & Marks \\\\\n\\hline\n");
+    for section in paper.sections.iter() {
+        table.push_str(&format!(
+            "{} & {} & {} & {} \\\\\n",
+            escape(section.title),
+            answer_rule(section),
+            section.time.map_or(String::new(), format_minutes),
+            section.marks
+        ));
+    }
+    table.push_str(&format!(
+        "\\hline\nTotal & & {} & {} \\\\\n\\end{{tabular}}",
+        format_minutes(paper.duration),
+        total
+    ));
+    cover.push(Element::UserDefined(table));
+    cover
+}
+
+// Editions with a cover open with the cover page, the others with a plain
+// title. Problems are numbered continuously across sections.
+pub fn make_exam(
+    paper: &ExamPaper,
+    date: &str,
+    metadata: &Metadata,
+    edition: &Edition,
+) -> Document {
+    let config = &edition.sheet_config;
+    let mut doc = make_sheet_doc(&paper.title, date, metadata, config);
+    let cover = edition.cover;
+
+    if cover {
+        for element in exam_cover(paper) {
+            doc.push(element);
+        }
+    } else {
+        doc.push(Element::UserDefined("\\maketitle".to_owned()));
+    }
+
+    if edition.parts.is_empty() {
+        return doc;
+    }
+    if cover {
+        doc.push(Element::ClearPage);
+    }
+
+    let mut start = 0;
+    for section in paper.sections.iter() {
+        doc.push(Element::UserDefined(format!(
+            "\\section*{{{}}}",
+            escape(section.title)
+        )));
+        let mut rule = format!("{}.", answer_rule(section));
+        if let Some(time) = section.time {
+            rule.push_str(&format!(" Suggested time: {}.", format_minutes(time)));
+        }
+        doc.push(Element::UserDefined(format!("\\textit{{{}}}", rule)));
+
+        if !section.entries.is_empty() {
+            let mut lines = vec![format!("\\setcounter{{enumi}}{{{}}}", start)];
+            lines.extend(
+                section
+                    .entries
+                    .iter()
+                    .map(|entry| edition_entry(entry, config)),
+            );
+            doc.push(Element::Environment("enumerate".to_owned(), lines));
+        }
+        start += section.entries.len();
+    }
+
+    doc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edition_entry() {
+        let entry = SheetEntry {
+            problem: "limits",
+            parts: vec![String::from("solution"), String::from(MARKSCHEME)],
+            marks: Some(4),
+        };
+        assert_eq!(
+            edition_entry(&entry, &SheetConfig::default()),
+            "\\item \\textbf{[4 marks]}\\par\n\\input{limits/solution.tex}\n\
+             \\par\\medskip\\textbf{Mark scheme}\\par\n\\input{limits/markscheme.tex}"
+        );
+
+        let config = SheetConfig {
+            problem_macro: Some(String::from("\\question")),
+            ..SheetConfig::default()
+        };
+        assert_eq!(edition_entry(&entry, &config), "\\question[4]{limits}");
+    }
+
+    #[test]
+    fn test_marks_grid() {
+        let entries: Vec<SheetEntry> = [Some(3), None]
+            .iter()
+            .map(|&marks| SheetEntry {
+                problem: "limits",
+                parts: vec![],
+                marks,
+            })
+            .collect();
+        assert_eq!(
+            marks_grid(&entries),
+            "\\begin{center}\n\\begin{tabular}{|l|c|c|c|}\n\\hline\n\
+             Question & 1 & 2 & Total \\\\\n\\hline\nAvailable & 3 & 0 & 3 \\\\\n\\hline\n\
+             Awarded & & & \\\\[1.5em]\n\\hline\n\\end{tabular}\n\\end{center}"
+        );
+    }
+
+    #[test]
+    fn test_handbook_levels() {
+        assert_eq!(handbook_levels("report"), ("chapter", "section"));
+        assert_eq!(handbook_levels("article"), ("section", "subsection"));
+    }
+
+    #[test]
+    fn test_handbook_titles() {
+        let items = [HandbookItem {
+            component: String::from("tutorials"),
+            title: String::from("$\\epsilon$-$\\delta$"),
+            intro: String::new(),
+            problems: vec![String::from("limits")],
+            marks: vec![],
+        }];
+        let metadata = Metadata {
+            author: String::from("A"),
+            other: HashMap::new(),
+        };
+        let config = SheetConfig::default();
+        let doc = make_handbook(&items, &metadata, "", &config, Some(&config));
+        for heading in &[
+            "\\chapter{$\\epsilon$-$\\delta$}",
+            "\\section{$\\epsilon$-$\\delta$}",
+        ] {
+            assert!(doc.contains(&Element::UserDefined(String::from(*heading))));
+        }
+    }
+
+    #[test]
+    fn test_format_minutes() {
+        assert_eq!(format_minutes(45), "45 minutes");
+        assert_eq!(format_minutes(60), "1 hour");
+        assert_eq!(format_minutes(150), "2 hours 30 minutes");
+    }
+
+    #[test]
+    fn test_exam_cover_title() {
+        let paper = ExamPaper {
+            title: String::from("$\\epsilon$-$\\delta$"),
+            duration: 60,
+            instructions: "",
+            sections: vec![],
+        };
+        match exam_cover(&paper)[0] {
+            Element::UserDefined(ref heading) => {
+                assert!(heading.contains("\\textbf{$\\epsilon$-$\\delta$}"))
+            }
+            _ => panic!("no cover heading"),
+        }
+    }
+
+    #[test]
+    fn test_coursework_cover_title() {
+        let cover = CourseworkCover {
+            due: None,
+            fields: &[],
+        };
+        let elements = coursework_cover("$\\epsilon$-$\\delta$", &cover, &[]);
+        match elements[0] {
+            Element::UserDefined(ref heading) => {
+                assert!(heading.contains("\\textbf{$\\epsilon$-$\\delta$}"))
+            }
+            _ => panic!("no cover heading"),
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use std::fmt::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use failure::bail;
+use log::info;
+
+use crate::backend::{BuildBackend, DocumentSources};
+use crate::engine::TexEngine;
+use crate::manifest::BuildManifest;
+use crate::TeachResult;
+
+fn perl_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn engine_settings(engine: &TexEngine) -> TeachResult<String> {
+    let (mode, var) = match engine.engine.as_str() {
+        "lualatex" => (4, "lualatex"),
+        "xelatex" => (5, "xelatex"),
+        _ => (1, "pdflatex"),
+    };
+
+    // tectonic does not understand the options latexmk passes in %O.
+    let command = if engine.is_tectonic() {
+        format!("{} {} %S", &engine.engine, engine.flags())
+    } else {
+        format!("{} {} %O %S", &engine.engine, engine.flags())
+    };
+
+    let mut rc = String::new();
+    writeln!(rc, "$pdf_mode = {};", mode)?;
+    writeln!(rc, "${} = {};", var, perl_quote(&command))?;
+    Ok(rc)
+}
+
+fn toplevel_makefile(components: &[&str]) -> TeachResult<String> {
+    let mut mf = String::new();
+    writeln!(mf, ".PHONY: all {}", components.join(" "))?;
+    writeln!(mf)?;
+    writeln!(mf, "all: {}", components.join(" "))?;
+    for comp in components.iter() {
+        writeln!(mf)?;
+        writeln!(mf, "{}:", comp)?;
+        writeln!(mf, "\tcd $@ && latexmk -r latexmkrc")?;
+    }
+    Ok(mf)
+}
+
+fn item_rc(item: &str) -> String {
+    format!("{}/{}.latexmkrc", item, item)
+}
+
+pub struct LatexmkBackend;
+
+impl BuildBackend for LatexmkBackend {
+    fn write_sheet(
+        &self,
+        name: &str,
+        root: &Path,
+        _documents: &[DocumentSources],
+        engine: &TexEngine,
+        manifest: &mut BuildManifest,
+    ) -> TeachResult<()> {
+        // latexmk records the dependencies of each document itself, only
+        // the engine can differ from the component.
+        manifest.write_file(
+            &root.join(format!("{}.latexmkrc", name)),
+            &engine_settings(engine)?,
+        )?;
+        Ok(())
+    }
+
+    fn write_component(
+        &self,
+        root: &Path,
+        _items: &[&str],
+        problems_dir: &str,
+        include_dirs: &[&str],
+        engine: &TexEngine,
+        manifest: &mut BuildManifest,
+    ) -> TeachResult<()> {
+        info!("Creating latexmkrc: {}", root.display());
+
+        let mut texinputs = String::from(problems_dir);
+        include_dirs.iter().for_each(|d| {
+            texinputs.push(':');
+            texinputs.push_str(d);
+        });
+        texinputs.push(':');
+
+        // latexmk only follows the converted LaTeX of Markdown problems,
+        // which is written by `teach build` or `teach convert`.
+        let mut rc = String::from(
+            "# Run `teach build` or `teach convert` after editing Markdown problems.\n",
+        );
+        rc.push_str(&engine_settings(engine)?);
+        writeln!(
+            rc,
+            "$ENV{{'TEXINPUTS'}} = {} . ($ENV{{'TEXINPUTS'}} || '');",
+            perl_quote(&texinputs)
+        )?;
+        writeln!(rc, "@default_files = ('*/*.tex');")?;
+
+        manifest.write_file(&root.join("latexmkrc"), &rc)?;
+        Ok(())
+    }
+
+    fn write_toplevel(
+        &self,
+        path: &Path,
+        components: &[&str],
+        manifest: &mut BuildManifest,
+    ) -> TeachResult<()> {
+        // latexmk has no way to recurse into components, so `make` at the
+        // top level runs it in each one.
+        manifest.write_file(&path.join("Makefile"), &toplevel_makefile(components)?)?;
+        Ok(())
+    }
+
+    fn compile(&self, dir: &Path, item: &str, outputs: &[String]) -> TeachResult<()> {
+        info!("Running latexmk in {}", dir.display());
+        let sources: Vec<String> = outputs
+            .iter()
+            .map(|out| format!("{}/{}.tex", item, out.trim_end_matches(".pdf")))
+            .collect();
+
+        let mut cmd = Command::new("latexmk");
+        cmd.current_dir(dir).arg("-r").arg("latexmkrc");
+        if dir.join(item_rc(item)).is_file() {
+            cmd.arg("-r").arg(item_rc(item));
+        }
+
+        let status = cmd
+            .args(&sources)
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()?;
+
+        if !status.success() {
+            bail!("latexmk failed in {}", dir.display());
+        }
+
+        Ok(())
+    }
+
+    fn toplevel_files(&self) -> Vec<String> {
+        vec![String::from("Makefile")]
+    }
+
+    fn component_files(&self) -> Vec<String> {
+        vec![String::from("latexmkrc")]
+    }
+
+    fn sheet_files(&self, name: &str) -> Vec<String> {
+        vec![format!("{}.latexmkrc", name)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tectonic_settings() {
+        let engine = TexEngine::new("tectonic", "-synctex=1");
+        assert_eq!(
+            engine_settings(&engine).unwrap(),
+            "$pdf_mode = 1;\n$pdflatex = 'tectonic --synctex --outdir . %S';\n"
+        );
+    }
+
+    #[test]
+    fn test_toplevel_makefile() {
+        assert_eq!(
+            toplevel_makefile(&["sheets", "exams"]).unwrap(),
+            ".PHONY: all sheets exams\n\nall: sheets exams\n\n\
+             sheets:\n\tcd $@ && latexmk -r latexmkrc\n\n\
+             exams:\n\tcd $@ && latexmk -r latexmkrc\n"
+        );
+    }
+}
@@ -2,11 +2,28 @@ use failure::Error;
 
 pub type TeachResult<T> = Result<T, Error>;
 
+pub mod backend;
+pub mod catalogue;
+pub mod clean;
 pub mod config;
 pub mod course;
 pub mod course_items;
+pub mod diff;
+pub mod engine;
+pub mod handbook;
+pub mod html;
+pub mod json;
 pub mod latexdoc;
+pub mod latexmk;
 pub mod makefile;
+pub mod manifest;
+pub mod markdown;
+pub mod moodle;
+pub mod ninja;
 pub mod preview;
+pub mod problem;
+pub mod qti;
+pub mod report;
+pub mod watch;
 
 pub use course::Course;
@@ -1,12 +1,20 @@
 use std::path::PathBuf;
 
+use failure::bail;
 use log::{self, info};
 use simple_logger;
 use structopt::StructOpt;
 use term_grid;
 use term_size;
 
-use teach::preview::Previewer;
+use teach::catalogue::GroupBy;
+use teach::clean;
+use teach::course::{BuildOptions, Selector};
+use teach::json;
+use teach::moodle;
+use teach::preview::{watch_preview, PreviewMode, PreviewTarget, Previewer};
+use teach::qti;
+use teach::watch::{self, Change};
 use teach::{Course, TeachResult};
 
 #[derive(StructOpt)]
@@ -21,10 +29,79 @@ struct EditInfo {
     touch: bool,
 }
 
+#[derive(StructOpt)]
+enum ExportCmd {
+    #[structopt(name = "moodle")]
+    Moodle {
+        #[structopt(help = "The item to export, as <component>/<item>")]
+        item: Selector,
+
+        #[structopt(
+            short = "o",
+            long = "output",
+            parse(from_os_str),
+            help = "Where to write the XML, <item>.xml by default."
+        )]
+        output: Option<PathBuf>,
+    },
+
+    #[structopt(name = "json")]
+    Json {
+        #[structopt(long = "schema", help = "Print the JSON schema of the export instead.")]
+        schema: bool,
+
+        #[structopt(
+            short = "o",
+            long = "output",
+            parse(from_os_str),
+            help = "Where to write the JSON, standard output by default."
+        )]
+        output: Option<PathBuf>,
+    },
+
+    #[structopt(name = "qti")]
+    Qti {
+        #[structopt(help = "The item to export, as <component>/<item>")]
+        item: Selector,
+
+        #[structopt(
+            short = "o",
+            long = "output",
+            parse(from_os_str),
+            help = "Where to write the package, <item>.zip by default."
+        )]
+        output: Option<PathBuf>,
+    },
+}
+
 #[derive(StructOpt)]
 enum Commands {
     #[structopt(name = "build")]
-    Build,
+    Build {
+        #[structopt(help = "Only build matching items, e.g. tutorials/week3 or courseworks/")]
+        selectors: Vec<Selector>,
+
+        #[structopt(short = "m", long = "make", help = "Compile the generated documents.")]
+        make: bool,
+
+        #[structopt(
+            short = "n",
+            long = "dry-run",
+            conflicts_with = "make",
+            help = "Show what would change without writing anything."
+        )]
+        dry_run: bool,
+
+        #[structopt(long = "diff", help = "Print a unified diff of each changed file.")]
+        diff: bool,
+
+        #[structopt(
+            short = "f",
+            long = "force",
+            help = "Overwrite generated files that were edited by hand."
+        )]
+        force: bool,
+    },
 
     #[structopt(name = "problem")]
     Problem(EditInfo),
@@ -33,13 +110,117 @@ enum Commands {
     Solution(EditInfo),
 
     #[structopt(name = "preview")]
-    Preview { name: String },
+    Preview {
+        #[structopt(
+            help = "Problems, globs such as 'limits-*', tag:<tag> queries, or one item as <component>/<item>"
+        )]
+        names: Vec<String>,
+
+        #[structopt(
+            long = "problem-only",
+            conflicts_with = "solution_only",
+            help = "Leave out the solutions."
+        )]
+        problem_only: bool,
+
+        #[structopt(
+            long = "solution-only",
+            help = "Only show the solutions, or an item's solutions document."
+        )]
+        solution_only: bool,
+
+        #[structopt(
+            short = "o",
+            long = "output",
+            parse(from_os_str),
+            help = "Write the PDF here and don't open a viewer."
+        )]
+        output: Option<PathBuf>,
+
+        #[structopt(
+            short = "w",
+            long = "watch",
+            help = "Rebuild the preview when sources change."
+        )]
+        watch: bool,
+    },
+
+    #[structopt(name = "watch")]
+    Watch,
+
+    #[structopt(name = "convert")]
+    Convert {
+        #[structopt(
+            parse(from_os_str),
+            help = "Markdown problem sources to convert to LaTeX"
+        )]
+        files: Vec<PathBuf>,
+    },
+
+    #[structopt(name = "catalogue")]
+    Catalogue {
+        #[structopt(
+            short = "g",
+            long = "group-by",
+            default_value = "prefix",
+            help = "Group problems by name prefix or by tag."
+        )]
+        group_by: GroupBy,
+
+        #[structopt(short = "s", long = "solutions", help = "Include the solutions.")]
+        solutions: bool,
+
+        #[structopt(
+            short = "o",
+            long = "output",
+            parse(from_os_str),
+            help = "Write the PDF here and don't open a viewer."
+        )]
+        output: Option<PathBuf>,
+    },
+
+    #[structopt(name = "handbook")]
+    Handbook {
+        #[structopt(
+            short = "s",
+            long = "solutions",
+            help = "Add the solutions in an appendix."
+        )]
+        solutions: bool,
+
+        #[structopt(
+            short = "o",
+            long = "output",
+            parse(from_os_str),
+            help = "Write the PDF here and don't open a viewer."
+        )]
+        output: Option<PathBuf>,
+    },
+
+    #[structopt(name = "export")]
+    Export {
+        #[structopt(subcommand)]
+        format: ExportCmd,
+    },
 
     #[structopt(name = "course")]
     CourseCmd,
 
     #[structopt(name = "problems")]
     Problems { problems: Vec<String> },
+
+    #[structopt(name = "clean")]
+    Clean,
+
+    #[structopt(name = "prune")]
+    Prune {
+        #[structopt(
+            short = "d",
+            long = "delete",
+            help = "Delete orphaned output instead of listing it."
+        )]
+        delete: bool,
+    },
 }
 
 #[derive(StructOpt)]
@@ -71,9 +252,21 @@ fn main() -> TeachResult<()> {
     simple_logger::init_with_level(level).unwrap();
 
     match opt.command {
-        Build => {
+        Build {
+            selectors,
+            make,
+            dry_run,
+            diff,
+            force,
+        } => {
             info!("Building course from {}", &opt.path.display());
-            cf.build()?;
+            cf.build(&BuildOptions {
+                selectors,
+                compile: make,
+                dry_run,
+                show_diff: diff,
+                force,
+            })?;
         }
         Problem(info) => {
             info!("Editing problem {}", &info.name);
@@ -83,11 +276,129 @@ fn main() -> TeachResult<()> {
             info!("Editing solution {}", &info.name);
             cf.edit_solution(&info.name, info.touch)?;
         }
-        Preview { name } => {
-            info!("Previewing problem {}", name);
-            let previewer = Previewer::new(&cf.path, &name, &cf.course_file.config);
-            previewer.preview()?;
+        Preview {
+            names,
+            problem_only,
+            solution_only,
+            output,
+            watch,
+        } => {
+            let item = match names.as_slice() {
+                [name] => name.parse::<Selector>().ok(),
+                _ => None,
+            };
+            let target = match item {
+                Some(Selector {
+                    component,
+                    item: Some(item),
+                }) => PreviewTarget::Item {
+                    component,
+                    name: item,
+                },
+                _ => {
+                    let problems = cf.find_problems(&names)?;
+                    if problems.is_empty() {
+                        bail!("No problems match {}", names.join(" "));
+                    }
+                    PreviewTarget::Problems(problems)
+                }
+            };
+            info!("Previewing {}", target);
+            let mode = match (problem_only, solution_only) {
+                (true, _) => PreviewMode::ProblemOnly,
+                (_, true) => PreviewMode::SolutionOnly,
+                _ => PreviewMode::Both,
+            };
+            if watch {
+                watch_preview(cf, target, mode, output)?;
+            } else {
+                let mut previewer = Previewer::new(&cf, target, mode);
+                if let Some(ref path) = output {
+                    previewer.set_output(path);
+                }
+                let report = previewer.preview()?;
+                if report.has_errors() {
+                    bail!("{} LaTeX error(s) in the preview", report.errors.len());
+                }
+            }
         }
+        Watch => {
+            let root = cf.path.clone();
+            let problems = cf.course_file.config.sources.problems.clone();
+            let mut course = cf;
+            course.build(&BuildOptions::default())?;
+
+            watch::watch(&root, &problems, |change| {
+                if let Change::CourseFile = change {
+                    info!("Reloading course file");
+                    course = Course::load(&root)?;
+                }
+                course.rebuild(change)
+            })?;
+        }
+        Convert { files } => {
+            for file in files.iter() {
+                cf.convert_markdown_file(file)?;
+            }
+        }
+        Catalogue {
+            group_by,
+            solutions,
+            output,
+        } => {
+            info!("Building problem catalogue grouped by {}", group_by);
+            let mode = if solutions {
+                PreviewMode::Both
+            } else {
+                PreviewMode::ProblemOnly
+            };
+            let mut previewer = Previewer::new(&cf, PreviewTarget::Catalogue(group_by), mode);
+            if let Some(ref path) = output {
+                previewer.set_output(path);
+            }
+            let report = previewer.preview()?;
+            if report.has_errors() {
+                bail!("{} LaTeX error(s) in the catalogue", report.errors.len());
+            }
+        }
+        Handbook { solutions, output } => {
+            info!("Building the course handbook");
+            let mode = if solutions {
+                PreviewMode::Both
+            } else {
+                PreviewMode::ProblemOnly
+            };
+            let mut previewer = Previewer::new(&cf, PreviewTarget::Handbook, mode);
+            if let Some(ref path) = output {
+                previewer.set_output(path);
+            }
+            let report = previewer.preview()?;
+            if report.has_errors() {
+                bail!("{} LaTeX error(s) in the handbook", report.errors.len());
+            }
+        }
+        Export { format } => match format {
+            ExportCmd::Moodle { item, output } => {
+                let output = output.unwrap_or_else(|| {
+                    let name = item.item.as_ref().unwrap_or(&item.component);
+                    PathBuf::from(format!("{}.xml", name))
+                });
+                info!("Exporting {} to Moodle XML", item);
+                moodle::export_moodle(&cf, &item, &output)?;
+            }
+            ExportCmd::Json { schema: true, .. } => print!("{}", json::SCHEMA),
+            ExportCmd::Json { output, .. } => {
+                json::export_json(&cf, output.as_deref())?;
+            }
+            ExportCmd::Qti { item, output } => {
+                let output = output.unwrap_or_else(|| {
+                    let name = item.item.as_ref().unwrap_or(&item.component);
+                    PathBuf::from(format!("{}.zip", name))
+                });
+                info!("Exporting {} to a QTI 2.1 package", item);
+                qti::export_qti(&cf, &item, &output)?;
+            }
+        },
         CourseCmd => {
             info!("Editing course file {}", cf.path.display());
             cf.edit_course_file()?;
@@ -107,6 +418,14 @@ fn main() -> TeachResult<()> {
                 println!("{}", grid.fit_into_width(w).unwrap());
             }
         }
+        Clean => {
+            info!("Removing intermediate files");
+            clean::clean(&cf)?;
+        }
+        Prune { delete } => {
+            info!("Looking for orphaned output");
+            clean::prune(&cf, delete)?;
+        }
     }
 
     Ok(())
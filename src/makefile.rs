@@ -1,11 +1,14 @@
 use std::fmt;
-use std::fs;
 use std::io::prelude::*;
 use std::path::Path;
+use std::process::{Command, Stdio};
 
+use failure::bail;
 use log::{info, trace};
 
-use crate::config::AppConfig;
+use crate::backend::{converted_source, BuildBackend, DocumentSources, MARKDOWN_DIR};
+use crate::engine::TexEngine;
+use crate::manifest::BuildManifest;
 use crate::TeachResult;
 
 pub struct MakeTarget<'a, T, P, R>
@@ -79,56 +82,87 @@ where
     }
 }
 
-pub fn write_sheet_makefile<S: AsRef<str>>(
+// Run the bibliography tool, and the extra pass it needs, only when the
+// first pass recorded citations.
+const BIB_RECIPE: &str = "@ if grep -qs '$(BIBCITE)' $(basename $@).$(BIBAUX); \
+                          then $(BIBTEX) $(basename $@); $(TEX) $(TEXFLAGS) $< > /dev/null; fi";
+
+fn make_var(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+pub fn write_sheet_makefile(
     name: &str,
     root: &Path,
-    problems: &[S],
+    documents: &[DocumentSources],
+    engine: &TexEngine,
+    manifest: &mut BuildManifest,
 ) -> TeachResult<()> {
-    let problem_rule = MakeTarget {
-        targets: &[format!("{}-problems.pdf", name)],
-        prereqs: &[
-            format!("{}-problems.tex", name),
-            String::from("$(PROBLEMS)"),
-        ],
-        recipe: &[
-            "@echo $<",
-            "@ $(TEX) $(TEXFLAGS) $< | checktex",
-            "@ $(TEX) $(TEXFLAGS) $< | checktex",
-            "@ $(RM) *.log *.aux",
-        ],
+    let outputs: Vec<String> = documents
+        .iter()
+        .map(|doc| format!("{}.pdf", doc.stem))
+        .collect();
+    let tex_engine = format!("TEX = {}", &engine.engine);
+    let tex_flags = format!("TEXFLAGS = {}", engine.flags());
+
+    let engine_rule = MakeTarget {
+        targets: &outputs,
+        prereqs: &[tex_engine],
+        recipe: &[] as &[&str],
     };
 
-    let solution_rule = MakeTarget {
-        targets: &[format!("{}-solutions.pdf", name)],
-        prereqs: &[
-            format!("{}-solutions.tex", name),
-            String::from("$(SOLUTIONS)"),
-        ],
-        recipe: &[
-            "@echo $<",
-            "@ $(TEX) $(TEXFLAGS) $< | checktex",
-            "@ $(TEX) $(TEXFLAGS) $< | checktex",
-            "@ $(RM) *.log *.aux",
-        ],
+    let flags_rule = MakeTarget {
+        targets: &outputs,
+        prereqs: &[tex_flags],
+        recipe: &[] as &[&str],
     };
 
-    let mut probs_var = String::from("PROBS = $(addprefix $(PROBDIR)/,");
-    for prob in problems.iter() {
-        probs_var.push(' ');
-        probs_var.push_str(prob.as_ref());
+    // One variable per document listing the sources it inputs, named
+    // after the edition suffix, e.g. SOLUTIONS.
+    let mut vars = vec![];
+    let mut prereqs = vec![];
+    for doc in documents.iter() {
+        let var = make_var(doc.stem[name.len()..].trim_start_matches('-'));
+        let mut value = format!("{} =", var);
+        for source in doc.sources.iter() {
+            match converted_source(source) {
+                Some(tex) => value.push_str(&format!(" $(MDDIR)/{}", tex)),
+                None => value.push_str(&format!(" $(PROBDIR)/{}", source)),
+            }
+        }
+        vars.push(value);
+        prereqs.push(vec![format!("{}.tex", doc.stem), format!("$({})", var)]);
+    }
+
+    let mut rules = vec![engine_rule, flags_rule];
+    for (output, prereqs) in outputs.iter().zip(prereqs.iter()) {
+        rules.push(MakeTarget {
+            targets: std::slice::from_ref(output),
+            prereqs,
+            recipe: &[
+                "@echo $<",
+                "@ $(TEX) $(TEXFLAGS) $< | checktex",
+                BIB_RECIPE,
+                "@ $(TEX) $(TEXFLAGS) $< | checktex",
+                "@ $(RM) *.log *.aux",
+            ],
+        });
     }
-    probs_var.push(')');
 
     let makefile = Makefile {
-        vars: &[
-            probs_var.as_str(),
-            "PROBLEMS = $(addsuffix /problem.tex, $(PROBS))",
-            "SOLUTIONS = $(addsuffix /solution.tex, $(PROBS))",
-        ],
-        rules: &[problem_rule, solution_rule],
+        vars: &vars,
+        rules: &rules,
     };
 
-    fs::write(root.join(format!("{}.mk", name)), makefile.to_string())?;
+    manifest.write_file(&root.join(format!("{}.mk", name)), &makefile.to_string())?;
 
     Ok(())
 }
@@ -137,10 +171,13 @@ pub fn write_component_makefile(
     path: &Path,
     problems_dir: &str,
     include_dirs: &[&str],
+    engine: &TexEngine,
+    manifest: &mut BuildManifest,
 ) -> TeachResult<()> {
     info!("Creating makefile: {}", path.display());
 
     let probdir = format!("PROBDIR={}", problems_dir);
+    let mddir = format!("MDDIR={}", MARKDOWN_DIR);
     let mut TEXINPUTS = String::from("export TEXINPUTS=");
     TEXINPUTS.push_str(problems_dir);
     include_dirs.iter().for_each(|d| {
@@ -149,16 +186,26 @@ pub fn write_component_makefile(
     });
     TEXINPUTS.push(':');
 
-    let config = AppConfig::get();
-    let tex_engine = format!("TEX = {}", &config.tex_engine);
-    let tex_flags = format!("TEXFLAGS = {}", &config.tex_flags);
+    let tex_engine = format!("TEX = {}", &engine.engine);
+    let tex_flags = format!("TEXFLAGS = {}", engine.flags());
+    let bibtex = format!("BIBTEX = {}", engine.bib_tool.command());
+    let bibaux = format!("BIBAUX = {}", engine.bib_tool.aux_extension());
+    let bibcite = format!(
+        "BIBCITE = {}",
+        engine.bib_tool.citation_marker().replace('\\', "\\\\")
+    );
 
     let vars = &[
         tex_engine.as_str(),
         tex_flags.as_str(),
+        bibtex.as_str(),
+        bibaux.as_str(),
+        bibcite.as_str(),
         "DIRS = $(wildcard */.)",
         "PDF_FILES = $(notdir $(patsubst %.tex, %.pdf, $(wildcard */*.tex)))",
         probdir.as_str(),
+        mddir.as_str(),
+        "TEACH = teach",
         "vpath %.tex $(DIRS)",
         TEXINPUTS.as_str(),
     ];
@@ -175,15 +222,26 @@ pub fn write_component_makefile(
                 prereqs: &["$(PDF_FILES)"],
                 recipe: &[""],
             },
+            // Documents depend on the converted LaTeX, so editing the
+            // Markdown converts it again.
+            MakeTarget {
+                targets: &["$(MDDIR)/%.tex"],
+                prereqs: &["$(PROBDIR)/%.md"],
+                recipe: &["@ $(TEACH) convert $<"],
+            },
         ],
     };
 
-    fs::write(path.join("Makefile"), format!("{}\n\ninclude */*.mk", mf))?;
+    manifest.write_file(&path.join("Makefile"), &format!("{}\n\ninclude */*.mk", mf))?;
 
     Ok(())
 }
 
-pub fn write_toplevel_makefile<S: AsRef<str>>(path: &Path, components: &[S]) -> TeachResult<()> {
+pub fn write_toplevel_makefile<S: AsRef<str>>(
+    path: &Path,
+    components: &[S],
+    manifest: &mut BuildManifest,
+) -> TeachResult<()> {
     let mut comp: Vec<&str> = components.iter().map(AsRef::as_ref).collect();
     trace!("Components: {:?}", &comp);
     let mut all_ = vec!["all"];
@@ -218,11 +276,80 @@ pub fn write_toplevel_makefile<S: AsRef<str>>(path: &Path, components: &[S]) ->
         rules: &rules,
     };
 
-    fs::write(path.join("Makefile"), mf.to_string())?;
+    manifest.write_file(&path.join("Makefile"), &mf.to_string())?;
 
     Ok(())
 }
 
+pub fn run_make<S: AsRef<str>>(dir: &Path, targets: &[S]) -> TeachResult<()> {
+    info!("Running make in {}", dir.display());
+    let status = Command::new("make")
+        .arg("-C")
+        .arg(dir)
+        .args(targets.iter().map(AsRef::as_ref))
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .status()?;
+
+    if !status.success() {
+        bail!("make failed in {}", dir.display());
+    }
+
+    Ok(())
+}
+
+pub struct MakeBackend;
+
+impl BuildBackend for MakeBackend {
+    fn write_sheet(
+        &self,
+        name: &str,
+        root: &Path,
+        documents: &[DocumentSources],
+        engine: &TexEngine,
+        manifest: &mut BuildManifest,
+    ) -> TeachResult<()> {
+        write_sheet_makefile(name, root, documents, engine, manifest)
+    }
+
+    fn write_component(
+        &self,
+        root: &Path,
+        _items: &[&str],
+        problems_dir: &str,
+        include_dirs: &[&str],
+        engine: &TexEngine,
+        manifest: &mut BuildManifest,
+    ) -> TeachResult<()> {
+        write_component_makefile(root, problems_dir, include_dirs, engine, manifest)
+    }
+
+    fn write_toplevel(
+        &self,
+        path: &Path,
+        components: &[&str],
+        manifest: &mut BuildManifest,
+    ) -> TeachResult<()> {
+        write_toplevel_makefile(path, components, manifest)
+    }
+
+    fn compile(&self, dir: &Path, _item: &str, outputs: &[String]) -> TeachResult<()> {
+        run_make(dir, outputs)
+    }
+
+    fn toplevel_files(&self) -> Vec<String> {
+        vec![String::from("Makefile")]
+    }
+
+    fn component_files(&self) -> Vec<String> {
+        vec![String::from("Makefile")]
+    }
+
+    fn sheet_files(&self, name: &str) -> Vec<String> {
+        vec![format!("{}.mk", name)]
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -239,4 +366,46 @@ mod tests {
 
         assert_eq!(mr.to_string(), expected);
     }
+
+    #[test]
+    fn test_tectonic_writes_pdf_to_component() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut manifest =
+            BuildManifest::load(&dir.path().join("manifest.toml"), dir.path()).unwrap();
+        manifest.set_dry_run(true);
+
+        let engine = TexEngine::new("tectonic", "-interaction=nonstopmode");
+        write_component_makefile(dir.path(), "../../problems", &[], &engine, &mut manifest)
+            .unwrap();
+
+        let makefile = &manifest.planned()[0].new;
+        assert!(makefile.contains("TEXFLAGS = --outdir .\n"));
+        assert!(makefile.contains("PDF_FILES = $(notdir "));
+    }
+
+    #[test]
+    fn test_markdown_prereqs_are_converted() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut manifest =
+            BuildManifest::load(&dir.path().join("manifest.toml"), dir.path()).unwrap();
+        manifest.set_dry_run(true);
+
+        let documents = [DocumentSources {
+            stem: String::from("week1-problems"),
+            sources: vec![String::from("a/problem.tex"), String::from("b/problem.md")],
+        }];
+        let engine = TexEngine::new("pdflatex", "");
+        write_sheet_makefile("week1", dir.path(), &documents, &engine, &mut manifest).unwrap();
+
+        let makefile = &manifest.planned()[0].new;
+        assert!(
+            makefile.starts_with("PROBLEMS = $(PROBDIR)/a/problem.tex $(MDDIR)/b/problem.tex\n")
+        );
+    }
+
+    #[test]
+    fn test_make_var() {
+        assert_eq!(make_var("solutions"), "SOLUTIONS");
+        assert_eq!(make_var("large-print"), "LARGE_PRINT");
+    }
 }
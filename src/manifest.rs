@@ -0,0 +1,313 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::{info, trace, warn};
+use md5;
+use serde::{Deserialize, Serialize};
+use toml;
+
+use crate::TeachResult;
+
+pub struct InputHasher {
+    context: md5::Context,
+}
+
+impl InputHasher {
+    pub fn new() -> InputHasher {
+        InputHasher {
+            context: md5::Context::new(),
+        }
+    }
+
+    pub fn update<T: AsRef<[u8]>>(&mut self, data: T) -> &mut InputHasher {
+        self.context.consume(data);
+        self
+    }
+
+    pub fn file(&mut self, path: &Path) -> TeachResult<&mut InputHasher> {
+        // Mark the file boundary so that moving content between files
+        // changes the hash.
+        if let Some(name) = path.file_name() {
+            self.context.consume(name.to_string_lossy().as_bytes());
+        }
+        if path.is_file() {
+            self.context.consume(fs::read(path)?);
+        }
+        Ok(self)
+    }
+
+    pub fn finish(self) -> String {
+        format!("{:x}", self.context.compute())
+    }
+}
+
+impl Default for InputHasher {
+    fn default() -> InputHasher {
+        InputHasher::new()
+    }
+}
+
+fn content_hash(contents: &[u8]) -> String {
+    format!("{:x}", md5::compute(contents))
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ManifestEntry {
+    pub inputs: String,
+
+    #[serde(default)]
+    pub output: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileStatus {
+    Created,
+    Modified,
+    Unchanged,
+    HandEdited,
+}
+
+impl fmt::Display for FileStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FileStatus::Created => write!(f, "created"),
+            FileStatus::Modified => write!(f, "modified"),
+            FileStatus::Unchanged => write!(f, "unchanged"),
+            FileStatus::HandEdited => write!(f, "hand-edited"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PlannedFile {
+    pub path: PathBuf,
+    pub status: FileStatus,
+    pub old: String,
+    pub new: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct BuildManifest {
+    #[serde(skip)]
+    path: PathBuf,
+
+    #[serde(skip)]
+    root: PathBuf,
+
+    #[serde(skip)]
+    dry_run: bool,
+
+    #[serde(skip)]
+    force: bool,
+
+    #[serde(skip)]
+    planned: Vec<PlannedFile>,
+
+    #[serde(skip)]
+    protected: Vec<PlannedFile>,
+
+    #[serde(default)]
+    files: BTreeMap<String, ManifestEntry>,
+}
+
+impl BuildManifest {
+    pub fn load(path: &Path, root: &Path) -> TeachResult<BuildManifest> {
+        let mut manifest: BuildManifest = if path.is_file() {
+            toml::from_str(&fs::read_to_string(path)?)?
+        } else {
+            trace!("No build manifest at {}", path.display());
+            BuildManifest::default()
+        };
+        manifest.path = path.to_owned();
+        manifest.root = root.to_owned();
+        Ok(manifest)
+    }
+
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    pub fn set_force(&mut self, force: bool) {
+        self.force = force;
+    }
+
+    pub fn planned(&self) -> &[PlannedFile] {
+        &self.planned
+    }
+
+    pub fn protected(&self) -> &[PlannedFile] {
+        &self.protected
+    }
+
+    pub fn save(&self) -> TeachResult<()> {
+        if self.dry_run {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent() {
+            if !dir.exists() {
+                fs::create_dir_all(dir)?;
+            }
+        }
+        fs::write(&self.path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    fn key(&self, path: &Path) -> String {
+        self.relative(path).to_string_lossy().into()
+    }
+
+    pub fn relative<'p>(&self, path: &'p Path) -> &'p Path {
+        path.strip_prefix(&self.root).unwrap_or(path)
+    }
+
+    pub fn create_dir(&self, path: &Path) -> TeachResult<()> {
+        if !self.dry_run && !path.exists() {
+            fs::create_dir(path)?;
+        }
+        Ok(())
+    }
+
+    fn is_hand_edited(&self, path: &Path, on_disk: &str) -> bool {
+        match self.files.get(&self.key(path)) {
+            Some(entry) => {
+                !entry.output.is_empty() && entry.output != content_hash(on_disk.as_bytes())
+            }
+            None => false,
+        }
+    }
+
+    fn plan(&mut self, path: &Path, contents: &str) -> TeachResult<bool> {
+        let (status, old) = if path.is_file() {
+            let old = fs::read_to_string(path)?;
+            if old == contents {
+                (FileStatus::Unchanged, old)
+            } else if self.is_hand_edited(path, &old) {
+                (FileStatus::HandEdited, old)
+            } else {
+                (FileStatus::Modified, old)
+            }
+        } else {
+            (FileStatus::Created, String::new())
+        };
+
+        self.planned.push(PlannedFile {
+            path: path.to_owned(),
+            status,
+            old,
+            new: contents.to_owned(),
+        });
+        Ok(status != FileStatus::Unchanged)
+    }
+
+    pub fn is_current(&self, path: &Path, inputs: &str) -> bool {
+        match self.files.get(&self.key(path)) {
+            Some(entry) => entry.inputs == inputs && path.is_file(),
+            None => false,
+        }
+    }
+
+    pub fn write(&mut self, path: &Path, inputs: &str, contents: &str) -> TeachResult<bool> {
+        if self.dry_run {
+            return self.plan(path, contents);
+        }
+
+        // A hand edit is reported even when the inputs are unchanged, and
+        // --force rewrites everything.
+        if !self.force && path.is_file() {
+            let on_disk = fs::read_to_string(path)?;
+            if self.is_hand_edited(path, &on_disk) {
+                warn!(
+                    "{} has been edited by hand, not overwriting",
+                    path.display()
+                );
+                self.protected.push(PlannedFile {
+                    path: path.to_owned(),
+                    status: FileStatus::HandEdited,
+                    old: on_disk,
+                    new: contents.to_owned(),
+                });
+                return Ok(false);
+            }
+        }
+
+        if !self.force && self.is_current(path, inputs) {
+            trace!("{} is up to date", path.display());
+            return Ok(false);
+        }
+
+        info!("Writing {}", path.display());
+        fs::write(path, contents)?;
+        self.files.insert(
+            self.key(path),
+            ManifestEntry {
+                inputs: inputs.to_owned(),
+                output: content_hash(contents.as_bytes()),
+            },
+        );
+        Ok(true)
+    }
+
+    pub fn forget(&mut self, path: &Path) {
+        let key = self.key(path);
+        let prefix = format!("{}/", key);
+        self.files
+            .retain(|file, _| file != &key && !file.starts_with(&prefix));
+    }
+
+    pub fn write_file(&mut self, path: &Path, contents: &str) -> TeachResult<bool> {
+        let mut hasher = InputHasher::new();
+        hasher.update(contents);
+        self.write(path, &hasher.finish(), contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unchanged_inputs_not_rewritten() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut manifest =
+            BuildManifest::load(&dir.path().join("manifest.toml"), dir.path()).unwrap();
+        let file = dir.path().join("sheet.tex");
+
+        assert!(manifest.write(&file, "abc", "first").unwrap());
+        assert!(!manifest.write(&file, "abc", "second").unwrap());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "first");
+
+        assert!(manifest.write(&file, "def", "third").unwrap());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "third");
+    }
+
+    #[test]
+    fn test_hand_edited_file_protected() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut manifest =
+            BuildManifest::load(&dir.path().join("manifest.toml"), dir.path()).unwrap();
+        let file = dir.path().join("sheet.tex");
+
+        manifest.write(&file, "abc", "generated").unwrap();
+        fs::write(&file, "fixed by hand").unwrap();
+
+        // Reported whether or not the inputs changed.
+        assert!(!manifest.write(&file, "abc", "generated").unwrap());
+        assert_eq!(manifest.protected().len(), 1);
+        manifest.protected.clear();
+
+        assert!(!manifest.write(&file, "def", "regenerated").unwrap());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "fixed by hand");
+        assert_eq!(manifest.protected().len(), 1);
+
+        manifest.set_force(true);
+        assert!(manifest.write(&file, "def", "regenerated").unwrap());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "regenerated");
+
+        // --force also restores a file whose inputs are unchanged.
+        fs::write(&file, "fixed again").unwrap();
+        assert!(manifest.write(&file, "def", "regenerated").unwrap());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "regenerated");
+    }
+}
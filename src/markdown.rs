@@ -0,0 +1,196 @@
+use std::path::Path;
+
+use pulldown_cmark::{Event, Options, Parser, Tag};
+
+use crate::latexdoc::escape;
+
+// Math is taken out before parsing, so that `_` and `*` in formulae are
+// not read as emphasis, and put back verbatim afterwards.
+fn protect_math(src: &str) -> (String, Vec<String>) {
+    let chars: Vec<char> = src.chars().collect();
+    let mut out = String::with_capacity(src.len());
+    let mut math = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                out.push(chars[i]);
+                out.push(chars[i + 1]);
+                i += 2;
+            }
+            // Leave code spans and fences alone.
+            '`' => {
+                let run = chars[i..].iter().take_while(|&&c| c == '`').count();
+                let fence: String = "`".repeat(run);
+                let rest: String = chars[i + run..].iter().collect();
+                let end = rest.find(&fence).map_or(rest.len(), |e| e + run);
+                out.push_str(&fence);
+                out.push_str(&rest[..end]);
+                i += run + rest[..end].chars().count();
+            }
+            '$' => {
+                let display = chars.get(i + 1) == Some(&'$');
+                let delim = if display { 2 } else { 1 };
+                let start = i + delim;
+                let mut end = start;
+                while end < chars.len() {
+                    if chars[end] == '\\' {
+                        end += 2;
+                        continue;
+                    }
+                    if chars[end] == '$' && (!display || chars.get(end + 1) == Some(&'$')) {
+                        break;
+                    }
+                    end += 1;
+                }
+                if end >= chars.len() {
+                    out.push('$');
+                    i += 1;
+                    continue;
+                }
+
+                let body: String = chars[start..end].iter().collect();
+                if display {
+                    math.push(format!("\\[{}\\]", body));
+                } else {
+                    math.push(format!("${}$", body));
+                }
+                out.push_str(&format!("TEACHMATH{}X", math.len() - 1));
+                i = end + delim;
+            }
+            c => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    (out, math)
+}
+
+fn restore_math(text: &str, math: &[String]) -> String {
+    let mut text = text.to_owned();
+    for (n, formula) in math.iter().enumerate().rev() {
+        text = text.replace(&format!("TEACHMATH{}X", n), formula);
+    }
+    text
+}
+
+pub fn has_images(src: &str) -> bool {
+    let (protected, _) = protect_math(src);
+    Parser::new_ext(&protected, Options::ENABLE_STRIKETHROUGH)
+        .any(|event| matches!(event, Event::Start(Tag::Image(..))))
+}
+
+// Documents are typeset from the component directory with the problems
+// directory on TEXINPUTS, so images are named from there.
+fn image_path(problem: &str, url: &str) -> String {
+    if Path::new(url).is_absolute() {
+        url.to_owned()
+    } else {
+        format!("{}/{}", problem, url)
+    }
+}
+
+pub fn to_latex(src: &str, problem: &str) -> String {
+    let (protected, math) = protect_math(src);
+    let mut out = String::new();
+    let mut in_code = false;
+    let mut in_image = false;
+
+    for event in Parser::new_ext(&protected, Options::ENABLE_STRIKETHROUGH) {
+        // The alt text of an image is not typeset.
+        if in_image {
+            in_image = !matches!(event, Event::End(Tag::Image(..)));
+            continue;
+        }
+        match event {
+            Event::Start(tag) => match tag {
+                Tag::Paragraph => {}
+                Tag::Heading(_) => out.push_str("\\par\\textbf{"),
+                Tag::BlockQuote => out.push_str("\\begin{quote}\n"),
+                Tag::CodeBlock(_) => {
+                    in_code = true;
+                    out.push_str("\\begin{verbatim}\n");
+                }
+                Tag::List(None) => out.push_str("\\begin{itemize}\n"),
+                Tag::List(Some(_)) => out.push_str("\\begin{enumerate}\n"),
+                Tag::Item => out.push_str("\\item "),
+                Tag::Emphasis => out.push_str("\\emph{"),
+                Tag::Strong => out.push_str("\\textbf{"),
+                Tag::Image(_, url, _) => {
+                    in_image = true;
+                    out.push_str(&format!(
+                        "\\includegraphics{{{}}}",
+                        image_path(problem, &url)
+                    ));
+                }
+                _ => {}
+            },
+            Event::End(tag) => match tag {
+                Tag::Paragraph => out.push_str("\n\n"),
+                Tag::Heading(_) => out.push_str("}\\par\n"),
+                Tag::BlockQuote => out.push_str("\\end{quote}\n"),
+                Tag::CodeBlock(_) => {
+                    in_code = false;
+                    out.push_str("\\end{verbatim}\n");
+                }
+                Tag::List(None) => out.push_str("\\end{itemize}\n"),
+                Tag::List(Some(_)) => out.push_str("\\end{enumerate}\n"),
+                Tag::Item => out.push('\n'),
+                Tag::Emphasis | Tag::Strong => out.push('}'),
+                Tag::Link(_, url, _) => {
+                    out.push_str(&format!(" (\\texttt{{{}}})", escape(&url)));
+                }
+                _ => {}
+            },
+            Event::Text(text) if in_code => out.push_str(&restore_math(&text, &math)),
+            Event::Text(text) => out.push_str(&restore_math(&escape(&text), &math)),
+            Event::Code(code) => {
+                let code = restore_math(&code, &math);
+                out.push_str(&format!("\\texttt{{{}}}", escape(&code)));
+            }
+            Event::SoftBreak => out.push('\n'),
+            Event::HardBreak => out.push_str("\\\\\n"),
+            Event::Rule => out.push_str("\\par\\noindent\\rule{\\linewidth}{0.4pt}\\par\n"),
+            _ => {}
+        }
+    }
+
+    out.trim_end().to_owned() + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markdown_to_latex() {
+        let latex = to_latex(
+            "Let $x_1 * y_2$ be **big** & positive.\n\n- one\n- $$a_n$$\n",
+            "limits",
+        );
+        assert_eq!(
+            latex,
+            "Let $x_1 * y_2$ be \\textbf{big} \\& positive.\n\n\
+             \\begin{itemize}\n\\item one\n\\item \\[a_n\\]\n\\end{itemize}\n"
+        );
+    }
+
+    #[test]
+    fn test_images_in_problem_dir() {
+        assert_eq!(
+            to_latex("See ![a *plot*](plot.png) here.\n", "limits"),
+            "See \\includegraphics{limits/plot.png} here.\n"
+        );
+    }
+
+    #[test]
+    fn test_has_images() {
+        assert!(has_images("A plot:\n\n![graph](plot.png)\n"));
+        assert!(!has_images(
+            "No plot, just $[0, 1]$ and [a link](https://example.com).\n"
+        ));
+    }
+}
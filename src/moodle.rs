@@ -0,0 +1,118 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use failure::bail;
+use log::{info, warn};
+
+use crate::course::Selector;
+use crate::html::{escape, Converter, MathDelimiters};
+use crate::Course;
+use crate::TeachResult;
+
+// The Moodle TeX notation filter only looks for double dollars. The
+// question text is HTML, so `<` in math is escaped like any other text.
+pub const MOODLE: MathDelimiters = MathDelimiters {
+    inline: ("$$", "$$"),
+    display: ("$$", "$$"),
+};
+
+const DEFAULT_GRADE: u32 = 1;
+
+fn cdata(text: &str) -> String {
+    format!("<![CDATA[{}]]>", text.replace("]]>", "]]]]><![CDATA[>"))
+}
+
+fn question(name: &str, text: &str, feedback: &str, grade: u32) -> TeachResult<String> {
+    let mut xml = String::new();
+    writeln!(xml, "  <question type=\"essay\">")?;
+    writeln!(xml, "    <name><text>{}</text></name>", escape(name))?;
+    writeln!(
+        xml,
+        "    <questiontext format=\"html\"><text>{}</text></questiontext>",
+        cdata(text)
+    )?;
+    writeln!(
+        xml,
+        "    <generalfeedback format=\"html\"><text>{}</text></generalfeedback>",
+        cdata(feedback)
+    )?;
+    writeln!(xml, "    <defaultgrade>{}</defaultgrade>", grade)?;
+    writeln!(xml, "    <penalty>0</penalty>")?;
+    writeln!(xml, "    <hidden>0</hidden>")?;
+    writeln!(xml, "    <responseformat>editor</responseformat>")?;
+    writeln!(xml, "    <responserequired>1</responserequired>")?;
+    writeln!(xml, "    <responsefieldlines>15</responsefieldlines>")?;
+    writeln!(xml, "    <attachments>0</attachments>")?;
+    writeln!(xml, "  </question>")?;
+    Ok(xml)
+}
+
+pub fn export_moodle(course: &Course, selector: &Selector, output: &Path) -> TeachResult<()> {
+    let name = match selector.item {
+        Some(ref name) => name,
+        None => bail!("Export needs an item, e.g. {}week1", selector),
+    };
+    let (_, item) = course.find_item(&selector.component, name)?;
+    let problems = course.get_problems(&item.problems())?;
+    let marks = item.marks();
+    let mut converter = Converter::new(MOODLE);
+
+    let mut xml = String::new();
+    writeln!(xml, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(xml, "<quiz>")?;
+    writeln!(xml, "  <question type=\"category\">")?;
+    writeln!(
+        xml,
+        "    <category><text>$course$/{}/{}</text></category>",
+        escape(&selector.component),
+        escape(name)
+    )?;
+    writeln!(xml, "  </question>")?;
+
+    for (n, prob) in problems.iter().enumerate() {
+        let text = converter.convert(&course.problem_latex(prob, "problem")?);
+        let feedback = converter.convert(&course.problem_latex(prob, "solution")?);
+        let grade = marks.get(n).cloned().unwrap_or(DEFAULT_GRADE);
+        xml.push_str(&question(prob, &text, &feedback, grade)?);
+    }
+    writeln!(xml, "</quiz>")?;
+
+    if !converter.unsupported().is_empty() {
+        let names: Vec<&str> = converter.unsupported().iter().map(AsRef::as_ref).collect();
+        warn!(
+            "{}: unsupported LaTeX left as text: {}",
+            selector,
+            names.join(", ")
+        );
+    }
+
+    fs::write(output, xml)?;
+    info!(
+        "Exported {} question(s) to {}",
+        problems.len(),
+        output.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_question_xml() {
+        let xml = question("limits", "<p>$$x$$</p>", "<p>]]></p>", 4).unwrap();
+        assert!(xml.contains("<text><![CDATA[<p>$$x$$</p>]]></text>"));
+        assert!(xml.contains("<![CDATA[<p>]]]]><![CDATA[></p>]]>"));
+        assert!(xml.contains("<defaultgrade>4</defaultgrade>"));
+    }
+
+    #[test]
+    fn test_moodle_math() {
+        let mut converter = Converter::new(MOODLE);
+        let html = converter.convert("If $a < b$ then\n\\[a \\& b > 0\\]");
+        assert!(html.contains("$$a &lt; b$$"));
+        assert!(html.contains("$$a \\&amp; b &gt; 0$$"));
+    }
+}
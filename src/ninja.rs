@@ -0,0 +1,246 @@
+use std::fmt::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use failure::bail;
+use log::info;
+
+use crate::backend::{converted_source, BuildBackend, DocumentSources};
+use crate::engine::TexEngine;
+use crate::manifest::BuildManifest;
+use crate::TeachResult;
+
+fn escape(s: &str) -> String {
+    s.replace('$', "$$").replace(' ', "$ ").replace(':', "$:")
+}
+
+pub struct NinjaBackend;
+
+impl BuildBackend for NinjaBackend {
+    fn write_sheet(
+        &self,
+        name: &str,
+        root: &Path,
+        documents: &[DocumentSources],
+        engine: &TexEngine,
+        manifest: &mut BuildManifest,
+    ) -> TeachResult<()> {
+        let mut ninja = String::new();
+        for doc in documents.iter() {
+            write!(
+                ninja,
+                "build {stem}.pdf: latex {name}/{stem}.tex |",
+                name = escape(name),
+                stem = escape(&doc.stem)
+            )?;
+            let mut markdown = vec![];
+            for source in doc.sources.iter() {
+                write!(ninja, " $probdir/{}", escape(source))?;
+                if converted_source(source).is_some() {
+                    markdown.push(format!("$probdir/{}", escape(source)));
+                }
+            }
+            writeln!(ninja)?;
+            // Converting in the edge rather than in build statements of its
+            // own, which items sharing a problem would both declare.
+            if !markdown.is_empty() {
+                writeln!(
+                    ninja,
+                    "  convert = $teach convert {} &&",
+                    markdown.join(" ")
+                )?;
+            }
+            writeln!(ninja, "  tex = {}", &engine.engine)?;
+            writeln!(ninja, "  texflags = {}", engine.flags())?;
+        }
+
+        manifest.write_file(&root.join(format!("{}.ninja", name)), &ninja)?;
+        Ok(())
+    }
+
+    fn write_component(
+        &self,
+        root: &Path,
+        items: &[&str],
+        problems_dir: &str,
+        include_dirs: &[&str],
+        engine: &TexEngine,
+        manifest: &mut BuildManifest,
+    ) -> TeachResult<()> {
+        info!("Creating build.ninja: {}", root.display());
+
+        let mut texinputs = String::from(problems_dir);
+        include_dirs.iter().for_each(|d| {
+            texinputs.push(':');
+            texinputs.push_str(d);
+        });
+        texinputs.push(':');
+
+        let mut ninja = String::new();
+        writeln!(ninja, "tex = {}", &engine.engine)?;
+        writeln!(ninja, "texflags = {}", escape(&engine.flags()))?;
+        writeln!(ninja, "bibtex = {}", engine.bib_tool.command())?;
+        writeln!(ninja, "bibaux = {}", engine.bib_tool.aux_extension())?;
+        writeln!(
+            ninja,
+            "bibcite = {}",
+            engine.bib_tool.citation_marker().replace('\\', "\\\\")
+        )?;
+        writeln!(ninja, "probdir = {}", escape(problems_dir))?;
+        writeln!(ninja, "teach = teach")?;
+        writeln!(ninja, "texinputs = {}", escape(&texinputs))?;
+        writeln!(ninja)?;
+        writeln!(ninja, "rule latex")?;
+        writeln!(
+            ninja,
+            "  command = $convert TEXINPUTS=$texinputs $tex $texflags $in > /dev/null && \
+             if grep -qs '$bibcite' $$(basename $out .pdf).$bibaux; \
+             then $bibtex $$(basename $out .pdf) > /dev/null && \
+             TEXINPUTS=$texinputs $tex $texflags $in > /dev/null; fi && \
+             TEXINPUTS=$texinputs $tex $texflags $in > /dev/null"
+        )?;
+        writeln!(ninja, "  description = LATEX $out")?;
+        writeln!(ninja)?;
+
+        // Taken from the course rather than the disk, so a dry run shows
+        // every fragment and removed items are dropped.
+        for item in items.iter() {
+            for fragment in self.sheet_files(item) {
+                writeln!(ninja, "include {}/{}", escape(item), escape(&fragment))?;
+            }
+        }
+
+        manifest.write_file(&root.join("build.ninja"), &ninja)?;
+        Ok(())
+    }
+
+    fn write_toplevel(
+        &self,
+        path: &Path,
+        components: &[&str],
+        manifest: &mut BuildManifest,
+    ) -> TeachResult<()> {
+        let mut ninja = String::new();
+        writeln!(ninja, "rule subninja")?;
+        writeln!(ninja, "  command = ninja -C $dir")?;
+        writeln!(ninja, "  description = NINJA $dir")?;
+        writeln!(ninja, "  pool = console")?;
+
+        for comp in components.iter() {
+            writeln!(ninja)?;
+            writeln!(ninja, "build {}/all: subninja", escape(comp))?;
+            writeln!(ninja, "  dir = {}", escape(comp))?;
+        }
+
+        manifest.write_file(&path.join("build.ninja"), &ninja)?;
+        Ok(())
+    }
+
+    fn compile(&self, dir: &Path, _item: &str, outputs: &[String]) -> TeachResult<()> {
+        info!("Running ninja in {}", dir.display());
+        let status = Command::new("ninja")
+            .arg("-C")
+            .arg(dir)
+            .args(outputs)
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::inherit())
+            .status()?;
+
+        if !status.success() {
+            bail!("ninja failed in {}", dir.display());
+        }
+
+        Ok(())
+    }
+
+    fn toplevel_files(&self) -> Vec<String> {
+        vec![String::from("build.ninja")]
+    }
+
+    fn component_files(&self) -> Vec<String> {
+        vec![String::from("build.ninja")]
+    }
+
+    fn sheet_files(&self, name: &str) -> Vec<String> {
+        vec![format!("{}.ninja", name)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sheet_converts_markdown() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut manifest =
+            BuildManifest::load(&dir.path().join("manifest.toml"), dir.path()).unwrap();
+        manifest.set_dry_run(true);
+
+        let documents = [DocumentSources {
+            stem: String::from("week1-problems"),
+            sources: vec![String::from("a/problem.tex"), String::from("b/problem.md")],
+        }];
+        NinjaBackend
+            .write_sheet(
+                "week1",
+                dir.path(),
+                &documents,
+                &TexEngine::new("pdflatex", ""),
+                &mut manifest,
+            )
+            .unwrap();
+
+        let ninja = &manifest.planned()[0].new;
+        assert!(ninja.starts_with(
+            "build week1-problems.pdf: latex week1/week1-problems.tex | \
+             $probdir/a/problem.tex $probdir/b/problem.md\n\
+             \x20 convert = $teach convert $probdir/b/problem.md &&\n"
+        ));
+    }
+
+    #[test]
+    fn test_component_includes_items() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut manifest =
+            BuildManifest::load(&dir.path().join("manifest.toml"), dir.path()).unwrap();
+        manifest.set_dry_run(true);
+
+        NinjaBackend
+            .write_component(
+                dir.path(),
+                &["week1", "week 2"],
+                "../../problems",
+                &[],
+                &TexEngine::new("pdflatex", ""),
+                &mut manifest,
+            )
+            .unwrap();
+
+        let ninja = &manifest.planned()[0].new;
+        assert!(ninja.ends_with("include week1/week1.ninja\ninclude week$ 2/week$ 2.ninja\n"));
+    }
+
+    #[test]
+    fn test_variables_are_escaped() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut manifest =
+            BuildManifest::load(&dir.path().join("manifest.toml"), dir.path()).unwrap();
+        manifest.set_dry_run(true);
+
+        NinjaBackend
+            .write_component(
+                dir.path(),
+                &[],
+                "../../my problems",
+                &["../../include"],
+                &TexEngine::new("pdflatex", "-jobname=$x"),
+                &mut manifest,
+            )
+            .unwrap();
+
+        let ninja = &manifest.planned()[0].new;
+        assert!(ninja.contains("\ntexflags = -jobname=$$x\n"));
+        assert!(ninja.contains("\ntexinputs = ../../my$ problems$:../../include$:\n"));
+    }
+}
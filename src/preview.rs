@@ -1,74 +1,283 @@
-use std::cell::RefCell;
+use std::fmt;
+use std::fs;
 use std::io::prelude::*;
-use std::io::BufReader;
 use std::path::{Path, PathBuf};
 use std::process::{Child as ChildProcess, Command, Stdio};
 
 use failure::bail;
 use latex;
 use log::{error, info, trace, warn};
-use outparse;
-use tempfile;
 
+use crate::catalogue::{build_catalogue, GroupBy};
 use crate::config::AppConfig;
 use crate::course_items::Config;
+use crate::engine::{BibTool, TexEngine};
+use crate::handbook::build_handbook;
 use crate::latexdoc;
-use crate::TeachResult;
+use crate::manifest::InputHasher;
+use crate::report::CompileReport;
+use crate::watch::{self, Change};
+use crate::{Course, TeachResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PreviewMode {
+    #[default]
+    Both,
+    ProblemOnly,
+    SolutionOnly,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PreviewTarget {
+    Problems(Vec<String>),
+    Item { component: String, name: String },
+    Catalogue(GroupBy),
+    Handbook,
+}
+
+impl PreviewTarget {
+    // Name of the cached build directory and PDF under .teach/preview.
+    fn key(&self, mode: PreviewMode) -> String {
+        let base = match self {
+            PreviewTarget::Problems(names) if names.len() == 1 => names[0].clone(),
+            PreviewTarget::Problems(names) => {
+                let mut hasher = InputHasher::new();
+                hasher.update(names.join("\n"));
+                format!("problems-{}", &hasher.finish()[..8])
+            }
+            PreviewTarget::Item { component, name } => format!("{}-{}", component, name),
+            PreviewTarget::Catalogue(group_by) => format!("catalogue-{}", group_by),
+            PreviewTarget::Handbook => String::from("handbook"),
+        };
+
+        match mode {
+            PreviewMode::Both => base,
+            PreviewMode::ProblemOnly => format!("{}-problem", base),
+            PreviewMode::SolutionOnly => format!("{}-solution", base),
+        }
+    }
+}
+
+impl fmt::Display for PreviewTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PreviewTarget::Problems(names) => write!(f, "{}", names.join(", ")),
+            PreviewTarget::Item { component, name } => write!(f, "{}/{}", component, name),
+            PreviewTarget::Catalogue(_) => write!(f, "catalogue"),
+            PreviewTarget::Handbook => write!(f, "handbook"),
+        }
+    }
+}
 
 pub struct Previewer<'a> {
     root: &'a Path,
-    problem: &'a str,
+    course: &'a Course,
+    target: PreviewTarget,
+    mode: PreviewMode,
     config: &'a Config,
-    temp_dir: RefCell<Option<tempfile::TempDir>>,
+    build_dir: PathBuf,
+    output: Option<PathBuf>,
 }
 
 impl<'a> Previewer<'a> {
-    pub fn new(root: &'a Path, problem: &'a str, config: &'a Config) -> Previewer<'a> {
+    pub fn new(course: &'a Course, target: PreviewTarget, mode: PreviewMode) -> Previewer<'a> {
         Previewer {
-            root,
-            problem,
-            config,
-            temp_dir: RefCell::new(None),
+            root: &course.path,
+            course,
+            config: &course.course_file.config,
+            build_dir: course.state_dir().join("preview").join(target.key(mode)),
+            target,
+            mode,
+            output: None,
+        }
+    }
+
+    // Write the PDF to `path` instead of the preview cache.
+    pub fn set_output(&mut self, path: &Path) {
+        self.output = Some(path.to_owned());
+    }
+
+    pub fn pdf_path(&self) -> PathBuf {
+        match self.output {
+            Some(ref path) => path.clone(),
+            None => PathBuf::from(format!("{}.pdf", self.build_dir.display())),
+        }
+    }
+
+    fn problems(&self) -> TeachResult<Vec<String>> {
+        match self.target {
+            PreviewTarget::Problems(ref names) => Ok(names.clone()),
+            PreviewTarget::Item {
+                ref component,
+                ref name,
+                ..
+            } => {
+                let (_, item) = self.course.find_item(component, name)?;
+                self.course.get_problems(&item.problems())
+            }
+            PreviewTarget::Catalogue(_) => self.course.get_problems::<&str>(&[]),
+            PreviewTarget::Handbook => Ok(build_handbook(self.course)?
+                .into_iter()
+                .flat_map(|item| item.problems)
+                .collect()),
         }
     }
 
-    fn create_temp_dir(&self) -> TeachResult<()> {
-        trace!("Creating temporary directory");
-        if let Some(_) = self.temp_dir.borrow().as_ref() {
-            return Ok(());
+    fn engine(&self) -> TeachResult<TexEngine> {
+        match self.target {
+            PreviewTarget::Problems(_) | PreviewTarget::Catalogue(_) | PreviewTarget::Handbook => {
+                Ok(self.config.engine(&[]))
+            }
+            PreviewTarget::Item {
+                ref component,
+                ref name,
+                ..
+            } => {
+                let (comp, item) = self.course.find_item(component, name)?;
+                Ok(self.config.engine(&[item.tex(), &comp.tex]))
+            }
         }
-        self.temp_dir.replace(Some(tempfile::TempDir::new()?));
+    }
+
+    fn create_build_dir(&self) -> TeachResult<()> {
+        trace!("Creating build directory {}", self.build_dir.display());
+        fs::create_dir_all(&self.build_dir)?;
         Ok(())
     }
 
-    fn create_preview_doc(&self) -> latex::Document {
+    // Replace the PDF in one step, so viewers reloading it never see a
+    // partially written file.
+    fn publish_pdf(&self) -> TeachResult<()> {
+        let built = self.build_dir.join("texput.pdf");
+        if !built.is_file() {
+            bail!("No PDF produced for {}", &self.target);
+        }
+
+        let dest = self.pdf_path();
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let partial = dest.with_extension("pdf.part");
+        fs::copy(&built, &partial)?;
+        fs::rename(&partial, &dest)?;
+        info!("Preview written to {}", dest.display());
+        Ok(())
+    }
+
+    fn create_preview_doc(&self) -> TeachResult<latex::Document> {
         trace!("Creating preview document");
-        latexdoc::make_preview_sheet(&self.problem, &self.config.sheet_config)
+        let mut doc = match self.target {
+            PreviewTarget::Problems(ref names) => {
+                let problems = Some(&self.config.sheet_config);
+                let solutions = Some(&self.config.solution_config);
+                match self.mode {
+                    PreviewMode::Both => latexdoc::make_preview_sheet(names, problems, solutions),
+                    PreviewMode::ProblemOnly => latexdoc::make_preview_sheet(names, problems, None),
+                    PreviewMode::SolutionOnly => {
+                        latexdoc::make_preview_sheet(names, None, solutions)
+                    }
+                }
+            }
+            // Use the documents `build` would write, without touching the
+            // year directory. Items have no combined document.
+            PreviewTarget::Item {
+                ref component,
+                ref name,
+            } => {
+                let (_, item) = self.course.find_item(component, name)?;
+                let part = match self.mode {
+                    PreviewMode::SolutionOnly => "solution",
+                    _ => "problem",
+                };
+                // The first edition inputting the part, e.g. the problems
+                // of a sheet or the paper of an exam.
+                let stem = match item
+                    .editions(self.config)
+                    .iter()
+                    .find(|edition| edition.parts.iter().any(|p| p == part))
+                {
+                    Some(edition) => edition.stem(name),
+                    None => bail!("No edition of {} has a {} part", &self.target, part),
+                };
+                match item
+                    .documents(name, self.course)?
+                    .into_iter()
+                    .find(|(doc_stem, _)| doc_stem == &stem)
+                {
+                    Some((_, doc)) => doc,
+                    None => bail!("No document to preview for {}", &self.target),
+                }
+            }
+            PreviewTarget::Catalogue(group_by) => {
+                let solutions = match self.mode {
+                    PreviewMode::ProblemOnly => None,
+                    _ => Some(&self.config.solution_config),
+                };
+                latexdoc::make_catalogue(
+                    &build_catalogue(self.course, group_by)?,
+                    &self.course.course_file.metadata,
+                    &self.course.year,
+                    &self.config.sheet_config,
+                    solutions,
+                )
+            }
+            PreviewTarget::Handbook => {
+                let solutions = match self.mode {
+                    PreviewMode::ProblemOnly => None,
+                    _ => Some(&self.config.solution_config),
+                };
+                latexdoc::make_handbook(
+                    &build_handbook(self.course)?,
+                    &self.course.course_file.metadata,
+                    &self.course.year,
+                    &self.config.documents.handbook,
+                    solutions,
+                )
+            }
+        };
+        if self.course.uses_graphics(&self.problems()?)? {
+            latexdoc::add_graphicx(&mut doc);
+        }
+        if let Some(ref bib) = self.config.bibliography {
+            let path = self.root.join(&bib.file);
+            latexdoc::add_bibliography(&mut doc, bib, &path.to_string_lossy());
+        }
+        if matches!(
+            self.target,
+            PreviewTarget::Catalogue(_) | PreviewTarget::Handbook
+        ) {
+            latexdoc::add_hyperref(&mut doc);
+        }
+        Ok(doc)
     }
 
     fn create_latex_command(&self) -> TeachResult<Command> {
-        let app_config = AppConfig::get();
+        let engine = self.engine()?;
 
         trace!("Creating latex command");
-        trace!("TeX engine: {}", &app_config.tex_engine);
+        trace!("TeX engine: {}", &engine.engine);
 
-        let mut cmd = Command::new(&app_config.tex_engine);
+        let mut cmd = Command::new(&engine.engine);
+        cmd.args(engine.args());
+        cmd.args(engine.stdin_args());
         cmd.stdin(Stdio::piped());
         cmd.stderr(Stdio::piped());
         cmd.stdout(Stdio::piped());
 
-        if let Some(dir) = self.temp_dir.borrow().as_ref() {
-            cmd.current_dir(&dir.path());
-        } else {
-            bail!("Temporary directory not created")
-        }
+        cmd.current_dir(&self.build_dir);
         let path = self.root.canonicalize()?;
         let problems_path = path.join(&self.config.sources.problems);
         let include_path = path.join("include");
+        let markdown_path = self.course.markdown_dir();
         trace!("Problems: {}", problems_path.display());
         trace!("Include: {}", include_path.display());
 
-        let texinputs = format!("{}:{}:", problems_path.display(), include_path.display());
+        let texinputs = format!(
+            "{}:{}:{}:",
+            problems_path.display(),
+            include_path.display(),
+            markdown_path.display()
+        );
         trace!("{}", texinputs);
 
         cmd.env("TEXINPUTS", texinputs);
@@ -76,16 +285,40 @@ impl<'a> Previewer<'a> {
         Ok(cmd)
     }
 
-    fn create_pdf(&self) -> TeachResult<()> {
+    fn run_bibliography(&self, tool: BibTool) -> TeachResult<bool> {
+        let dir = &self.build_dir;
+        if !tool.needs_run(dir, "texput")? {
+            return Ok(false);
+        }
+
+        info!("Running {}", tool.command());
+        let output = Command::new(tool.command())
+            .current_dir(dir)
+            .arg("texput")
+            .output()?;
+        if !output.status.success() {
+            warn!("{} failed", tool.command());
+        }
+
+        Ok(true)
+    }
+
+    fn create_pdf(&self) -> TeachResult<CompileReport> {
         trace!("Creating preview PDF file");
-        self.create_temp_dir()?;
+        self.create_build_dir()?;
+        self.course.convert_markdown(&self.problems()?)?;
         let mut cmd = self.create_latex_command()?;
+        let bib_tool = self.engine()?.bib_tool;
 
-        let doc = self.create_preview_doc();
+        let doc = self.create_preview_doc()?;
         trace!("{:?}", &cmd);
 
+        let mut bib_done = self.config.bibliography.is_none();
+        let passes = if bib_done { 2 } else { 3 };
+
         let mut child: ChildProcess;
-        for i in 0..2 {
+        let mut report: Option<CompileReport> = None;
+        for i in 0..passes {
             trace!("Build {}", i);
             child = cmd.spawn()?;
 
@@ -95,51 +328,163 @@ impl<'a> Previewer<'a> {
                 bail!("Something went horribly wrong!")
             }
 
-            child.wait()?;
-            if let Some(stdout) = child.stdout {
-                let rd = BufReader::new(stdout);
-                let report = outparse::parse_log(rd);
-                info!("{}: {}", &self.problem, &report);
-                for message in &report.messages {
-                    use outparse::Message::*;
-                    match message {
-                        Error(i) => warn!("Error: {}", i.full),
-                        Warning(i) => info!("Warning: {}", i.full),
-                        Badbox(i) => trace!("Badbox: {}", i.full),
-                        MissingCitation { label } => info!("Missing citation: {}", label),
-                        MissingReference { label } => info!("Missing reference: {}", label),
-                        _ => {}
-                    };
-                }
+            let output = child.wait_with_output()?;
+            let log = String::from_utf8_lossy(&output.stdout);
+            let pass = CompileReport::from_log(&log, &self.build_dir, self.course);
+            info!("{}: {}", &self.target, &pass.log);
+            for message in &pass.log.messages {
+                use outparse::Message::*;
+                match message {
+                    Warning(i) => info!("Warning: {}", i.full),
+                    Badbox(i) => trace!("Badbox: {}", i.full),
+                    MissingCitation { label } => info!("Missing citation: {}", label),
+                    MissingReference { label } => info!("Missing reference: {}", label),
+                    _ => {}
+                };
+            }
+
+            // The table of contents is only filled in on the second pass.
+            let has_toc = match self.target {
+                PreviewTarget::Catalogue(_) | PreviewTarget::Handbook => i == 0,
+                _ => false,
+            };
+            let rerun =
+                has_toc || pass.log.missing_references > 0 || pass.log.missing_citations > 0;
+            report = Some(pass);
 
-                if report.missing_references == 0 && report.missing_citations == 0 {
-                    break;
+            if !bib_done {
+                bib_done = true;
+                if self.run_bibliography(bib_tool)? {
+                    continue;
                 }
             }
+
+            if !rerun {
+                break;
+            }
         }
 
-        Ok(())
+        let report = match report {
+            Some(report) => report,
+            None => bail!("{} was not compiled", &self.target),
+        };
+        for err in &report.errors {
+            error!("{}", err);
+        }
+
+        // Keep the last good PDF when TeX gave up without one.
+        if report.has_errors() && !self.build_dir.join("texput.pdf").is_file() {
+            return Ok(report);
+        }
+        self.publish_pdf()?;
+        Ok(report)
     }
 
-    fn open_viewer(&self) -> TeachResult<()> {
+    fn create_viewer_command(&self) -> TeachResult<Command> {
         let app_config = AppConfig::get();
         let mut cmd = Command::new(&app_config.pdf_viewer);
         cmd.stderr(Stdio::null());
         cmd.stderr(Stdio::null());
-        if let Some(dir) = self.temp_dir.borrow().as_ref() {
-            cmd.current_dir(&dir.path());
-        } else {
-            bail!("Temporary directory not created")
-        }
-        cmd.arg("texput.pdf");
+        cmd.arg(self.pdf_path());
+
+        Ok(cmd)
+    }
 
-        cmd.output()?;
+    fn open_viewer(&self) -> TeachResult<()> {
+        self.create_viewer_command()?.output()?;
 
         Ok(())
     }
 
-    pub fn preview(&self) -> TeachResult<()> {
-        self.create_pdf()?;
-        self.open_viewer()
+    pub fn preview(&self) -> TeachResult<CompileReport> {
+        let report = self.create_pdf()?;
+        if self.output.is_none() && !report.has_errors() {
+            self.open_viewer()?;
+        }
+        Ok(report)
+    }
+}
+
+// Previews again whenever one of the target's problems changes. The
+// course file is reloaded when it changes, so the preview follows edits
+// to items and configuration.
+pub fn watch_preview(
+    course: Course,
+    target: PreviewTarget,
+    mode: PreviewMode,
+    output: Option<PathBuf>,
+) -> TeachResult<()> {
+    fn previewer<'a>(
+        course: &'a Course,
+        target: &PreviewTarget,
+        mode: PreviewMode,
+        output: &Option<PathBuf>,
+    ) -> Previewer<'a> {
+        let mut previewer = Previewer::new(course, target.clone(), mode);
+        if let Some(ref path) = output {
+            previewer.set_output(path);
+        }
+        previewer
+    }
+
+    {
+        let first = previewer(&course, &target, mode, &output);
+        first.create_pdf()?;
+        if first.output.is_none() && first.pdf_path().is_file() {
+            first.create_viewer_command()?.spawn()?;
+        }
+    }
+
+    let root = course.path.clone();
+    let problems_dir = course.course_file.config.sources.problems.clone();
+    let mut course = course;
+    watch::watch(&root, &problems_dir, |change| {
+        if let Change::CourseFile = change {
+            info!("Reloading course file");
+            course = Course::load(&root)?;
+        }
+        let current = previewer(&course, &target, mode, &output);
+        if change.affects_any(&current.problems()?) {
+            current.create_pdf().map(|_| ())
+        } else {
+            Ok(())
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::course::test_course;
+
+    #[test]
+    fn test_preview_exam_item() {
+        let (_dir, course) = test_course(
+            "[finals.may]\ntitle = \"May exam\"\ntopic = \"Limits\"\nduration = 120\n\
+             instructions = \"Answer all questions.\"\n\
+             [[finals.may.sections]]\ntitle = \"Section A\"\nproblems = [\"limits\"]\nmarks = [10]\n",
+            &[
+                ("problems/limits/problem.tex", "problem"),
+                ("problems/limits/solution.tex", "solution"),
+                ("problems/limits/markscheme.tex", "markscheme"),
+            ],
+        );
+        let inputs = |mode| {
+            let target = PreviewTarget::Item {
+                component: String::from("finals"),
+                name: String::from("may"),
+            };
+            let doc = Previewer::new(&course, target, mode)
+                .create_preview_doc()
+                .unwrap();
+            doc.iter()
+                .filter_map(|element| match element {
+                    latex::Element::Environment(_, lines) => Some(lines.join("\n")),
+                    _ => None,
+                })
+                .collect::<String>()
+        };
+        assert!(inputs(PreviewMode::ProblemOnly).contains("\\input{limits/problem.tex}"));
+        assert!(inputs(PreviewMode::SolutionOnly).contains("\\input{limits/solution.tex}"));
     }
 }
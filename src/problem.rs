@@ -0,0 +1,80 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use toml;
+
+use crate::TeachResult;
+
+pub const META_FILE: &str = "meta.toml";
+
+// Every problem has these, other parts such as a mark scheme are optional.
+pub const REQUIRED_PARTS: &[&str] = &["problem", "solution"];
+
+pub const MARKSCHEME: &str = "markscheme";
+
+// Sources of a problem relative to the problems directory, either LaTeX
+// or Markdown.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProblemFiles {
+    pub problem: String,
+    pub solution: String,
+    pub markscheme: Option<String>,
+}
+
+// The `.tex` file wins when both exist.
+pub fn source_file(problem_dir: &Path, part: &str) -> PathBuf {
+    let tex = problem_dir.join(format!("{}.tex", part));
+    let md = problem_dir.join(format!("{}.md", part));
+    if !tex.is_file() && md.is_file() {
+        md
+    } else {
+        tex
+    }
+}
+
+pub fn is_markdown(path: &Path) -> bool {
+    matches!(path.extension(), Some(ext) if ext == "md")
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct ProblemMeta {
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    #[serde(flatten)]
+    pub other: BTreeMap<String, toml::Value>,
+}
+
+impl ProblemMeta {
+    // Problems without a meta.toml simply have no tags.
+    pub fn load(problem_dir: &Path) -> TeachResult<ProblemMeta> {
+        let path = problem_dir.join(META_FILE);
+        if !path.is_file() {
+            return Ok(ProblemMeta::default());
+        }
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_meta() {
+        let meta: ProblemMeta =
+            toml::from_str("tags = [\"limits\", \"easy\"]\nauthor = \"JM\"\n").unwrap();
+        assert!(meta.has_tag("limits"));
+        assert!(!meta.has_tag("series"));
+        assert_eq!(
+            meta.other.get("author"),
+            Some(&toml::Value::String(String::from("JM")))
+        );
+    }
+}
@@ -0,0 +1,294 @@
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use failure::bail;
+use log::{info, warn};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::course::Selector;
+use crate::html::{escape, Converter, MATHJAX};
+use crate::Course;
+use crate::TeachResult;
+
+// Both documents carry schemaLocation hints, so a package can be checked
+// with xmllint against local copies of the IMS schemas, see README.md.
+const QTI_NS: &str = "http://www.imsglobal.org/xsd/imsqti_v2p1";
+const QTI_XSD: &str = "http://www.imsglobal.org/xsd/qti/qtiv2p1/imsqti_v2p1.xsd";
+const CP_NS: &str = "http://www.imsglobal.org/xsd/imscp_v1p1";
+const CP_XSD: &str = "http://www.imsglobal.org/xsd/qti/qtiv2p1/qtiv2p1_imscpv1p2_v1p0.xsd";
+const XSI_NS: &str = "http://www.w3.org/2001/XMLSchema-instance";
+
+const DEFAULT_MARKS: u32 = 1;
+
+// Identifiers are NCNames, so they cannot start with a digit or contain
+// most punctuation.
+fn identifier(prefix: &str, name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{}-{}", prefix, name)
+}
+
+// An essay question, with the solution shown as feedback once answered.
+fn item_xml(
+    ident: &str,
+    title: &str,
+    problem: &str,
+    solution: &str,
+    marks: u32,
+) -> TeachResult<String> {
+    let mut xml = String::new();
+    writeln!(xml, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(
+        xml,
+        "<assessmentItem xmlns=\"{}\" xmlns:xsi=\"{}\" xsi:schemaLocation=\"{} {}\"",
+        QTI_NS, XSI_NS, QTI_NS, QTI_XSD
+    )?;
+    writeln!(
+        xml,
+        "    identifier=\"{}\" title=\"{}\" adaptive=\"false\" timeDependent=\"false\">",
+        ident,
+        escape(title)
+    )?;
+    writeln!(
+        xml,
+        "  <responseDeclaration identifier=\"RESPONSE\" cardinality=\"single\" baseType=\"string\"/>"
+    )?;
+    writeln!(
+        xml,
+        "  <outcomeDeclaration identifier=\"SCORE\" cardinality=\"single\" baseType=\"float\" normalMaximum=\"{}\"/>",
+        marks
+    )?;
+    writeln!(
+        xml,
+        "  <outcomeDeclaration identifier=\"FEEDBACK\" cardinality=\"single\" baseType=\"identifier\"/>"
+    )?;
+    writeln!(xml, "  <itemBody>")?;
+    writeln!(xml, "<div>\n{}\n</div>", problem)?;
+    writeln!(
+        xml,
+        "    <extendedTextInteraction responseIdentifier=\"RESPONSE\" expectedLines=\"15\"/>"
+    )?;
+    writeln!(xml, "  </itemBody>")?;
+    writeln!(xml, "  <responseProcessing>")?;
+    writeln!(xml, "    <setOutcomeValue identifier=\"FEEDBACK\">")?;
+    writeln!(
+        xml,
+        "      <baseValue baseType=\"identifier\">SOLUTION</baseValue>"
+    )?;
+    writeln!(xml, "    </setOutcomeValue>")?;
+    writeln!(xml, "  </responseProcessing>")?;
+    writeln!(
+        xml,
+        "  <modalFeedback outcomeIdentifier=\"FEEDBACK\" showHide=\"show\" identifier=\"SOLUTION\" title=\"Solution\">"
+    )?;
+    writeln!(xml, "<div>\n{}\n</div>", solution)?;
+    writeln!(xml, "  </modalFeedback>")?;
+    writeln!(xml, "</assessmentItem>")?;
+    Ok(xml)
+}
+
+fn manifest_xml(ident: &str, items: &[String]) -> TeachResult<String> {
+    let mut xml = String::new();
+    writeln!(xml, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(
+        xml,
+        "<manifest xmlns=\"{}\" xmlns:xsi=\"{}\" xsi:schemaLocation=\"{} {}\" identifier=\"{}\">",
+        CP_NS, XSI_NS, CP_NS, CP_XSD, ident
+    )?;
+    writeln!(xml, "  <metadata>")?;
+    writeln!(xml, "    <schema>QTIv2.1 Package</schema>")?;
+    writeln!(xml, "    <schemaversion>1.0.0</schemaversion>")?;
+    writeln!(xml, "  </metadata>")?;
+    writeln!(xml, "  <organizations/>")?;
+    writeln!(xml, "  <resources>")?;
+    for item in items {
+        writeln!(
+            xml,
+            "    <resource identifier=\"{0}\" type=\"imsqti_item_xmlv2p1\" href=\"items/{0}.xml\">",
+            item
+        )?;
+        writeln!(xml, "      <file href=\"items/{}.xml\"/>", item)?;
+        writeln!(xml, "    </resource>")?;
+    }
+    writeln!(xml, "  </resources>")?;
+    writeln!(xml, "</manifest>")?;
+    Ok(xml)
+}
+
+pub fn export_qti(course: &Course, selector: &Selector, output: &Path) -> TeachResult<()> {
+    let name = match selector.item {
+        Some(ref name) => name,
+        None => bail!("Export needs an item, e.g. {}week1", selector),
+    };
+    let (_, item) = course.find_item(&selector.component, name)?;
+    let problems = course.get_problems(&item.problems())?;
+    let marks = item.marks();
+    let mut converter = Converter::new(MATHJAX);
+
+    let mut zip = ZipWriter::new(File::create(output)?);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let mut items = vec![];
+    for (n, prob) in problems.iter().enumerate() {
+        let ident = identifier(&format!("Q{}", n + 1), prob);
+        let problem = converter.convert(&course.problem_latex(prob, "problem")?);
+        let solution = converter.convert(&course.problem_latex(prob, "solution")?);
+        let marks = marks.get(n).cloned().unwrap_or(DEFAULT_MARKS);
+        let title = format!("{}, problem {}", item.title(), n + 1);
+
+        zip.start_file(format!("items/{}.xml", ident), options)?;
+        zip.write_all(item_xml(&ident, &title, &problem, &solution, marks)?.as_bytes())?;
+        items.push(ident);
+    }
+
+    let ident = identifier("MANIFEST", &format!("{}-{}", selector.component, name));
+    zip.start_file("imsmanifest.xml", options)?;
+    zip.write_all(manifest_xml(&ident, &items)?.as_bytes())?;
+    zip.finish()?;
+
+    if !converter.unsupported().is_empty() {
+        let names: Vec<&str> = converter.unsupported().iter().map(AsRef::as_ref).collect();
+        warn!(
+            "{}: unsupported LaTeX left as text: {}",
+            selector,
+            names.join(", ")
+        );
+    }
+
+    info!(
+        "Exported {} item(s) to {}",
+        problems.len(),
+        output.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    use roxmltree::Document;
+    use zip::ZipArchive;
+
+    use crate::course::test_course;
+
+    #[test]
+    fn test_identifier() {
+        assert_eq!(identifier("Q1", "limits-squeeze"), "Q1-limits-squeeze");
+        assert_eq!(identifier("Q2", "3 series/ratio"), "Q2-3_series_ratio");
+    }
+
+    #[test]
+    fn test_item_xml() {
+        let xml = item_xml("Q1-limits", "Sheet <1>", "<p>x</p>", "<p>y</p>", 4).unwrap();
+        assert!(xml.contains("identifier=\"Q1-limits\" title=\"Sheet &lt;1&gt;\""));
+        assert!(xml.contains("normalMaximum=\"4\""));
+        assert!(xml.contains("<div>\n<p>y</p>\n</div>\n  </modalFeedback>"));
+    }
+
+    fn attr<'a>(node: roxmltree::Node<'a, '_>, name: &str) -> &'a str {
+        node.attribute(name)
+            .unwrap_or_else(|| panic!("{} has no {}", node.tag_name().name(), name))
+    }
+
+    fn child<'a, 'i>(node: roxmltree::Node<'a, 'i>, name: &str) -> roxmltree::Node<'a, 'i> {
+        node.descendants()
+            .find(|n| n.tag_name().name() == name)
+            .unwrap_or_else(|| panic!("{} has no {}", node.tag_name().name(), name))
+    }
+
+    // Checks the parts of the QTI and content packaging schemas that
+    // importers rely on: well-formed XML in the right namespaces, the
+    // required attributes, and identifiers that refer to each other.
+    #[test]
+    fn test_package_structure() {
+        let problem =
+            "Show $a < b$ \\& \\textbf{c}~d.\n\n\\begin{enumerate}\\item x\\end{enumerate}\n";
+        let solution = "\\[ x^2 > 0 \\]\n";
+        let (_dir, course) = test_course(
+            "[tutorials.week1]\ntitle = \"Week <1> & 2\"\ntopic = \"Limits\"\n\
+             problems = [\"limits\", \"2-series\"]\n",
+            &[
+                ("problems/limits/problem.tex", problem),
+                ("problems/limits/solution.tex", solution),
+                ("problems/2-series/problem.tex", problem),
+                ("problems/2-series/solution.tex", solution),
+            ],
+        );
+        let root = &course.path;
+        let output = root.join("week1.zip");
+        export_qti(&course, &"tutorials/week1".parse().unwrap(), &output).unwrap();
+
+        let mut zip = ZipArchive::new(File::open(&output).unwrap()).unwrap();
+        let mut files = std::collections::HashMap::new();
+        for i in 0..zip.len() {
+            let mut file = zip.by_index(i).unwrap();
+            let mut contents = String::new();
+            file.read_to_string(&mut contents).unwrap();
+            files.insert(file.name().to_owned(), contents);
+        }
+
+        let manifest = Document::parse(&files["imsmanifest.xml"]).unwrap();
+        let manifest = manifest.root_element();
+        assert_eq!(manifest.tag_name().name(), "manifest");
+        assert_eq!(manifest.tag_name().namespace(), Some(CP_NS));
+        assert!(attr(manifest, "identifier").starts_with("MANIFEST-"));
+        assert_eq!(child(manifest, "schema").text(), Some("QTIv2.1 Package"));
+
+        let resources: Vec<_> = child(manifest, "resources")
+            .children()
+            .filter(|n| n.is_element())
+            .collect();
+        assert_eq!(resources.len(), 2);
+        assert_eq!(files.len(), 3);
+        for resource in resources {
+            assert_eq!(attr(resource, "type"), "imsqti_item_xmlv2p1");
+            let href = attr(resource, "href");
+            assert_eq!(attr(child(resource, "file"), "href"), href);
+
+            let item = Document::parse(&files[href]).unwrap();
+            let item = item.root_element();
+            assert_eq!(item.tag_name().name(), "assessmentItem");
+            assert_eq!(item.tag_name().namespace(), Some(QTI_NS));
+            assert_eq!(attr(item, "identifier"), attr(resource, "identifier"));
+            assert!(attr(item, "title").starts_with("Week <1> & 2, problem "));
+            assert_eq!(attr(item, "adaptive"), "false");
+            assert_eq!(attr(item, "timeDependent"), "false");
+
+            let response = child(item, "responseDeclaration");
+            let interaction = child(child(item, "itemBody"), "extendedTextInteraction");
+            assert_eq!(
+                attr(interaction, "responseIdentifier"),
+                attr(response, "identifier")
+            );
+
+            let outcomes: Vec<&str> = item
+                .children()
+                .filter(|n| n.tag_name().name() == "outcomeDeclaration")
+                .map(|n| attr(n, "identifier"))
+                .collect();
+            let feedback = child(item, "modalFeedback");
+            assert!(outcomes.contains(&attr(feedback, "outcomeIdentifier")));
+            assert_eq!(attr(child(item, "baseValue"), "baseType"), "identifier");
+            assert_eq!(
+                child(item, "baseValue").text(),
+                Some(attr(feedback, "identifier"))
+            );
+            assert!(child(item, "itemBody")
+                .descendants()
+                .any(|n| matches!(n.text(), Some(t) if t.contains("\\(a < b\\)"))));
+        }
+    }
+}
@@ -0,0 +1,330 @@
+use std::fmt;
+use std::fs;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use outparse::{self, LogFileReport};
+
+use crate::Course;
+
+const CONTEXT_LINES: usize = 2;
+
+// TeX breaks terminal output at this many characters.
+const MAX_PRINT_LINE: usize = 79;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLocation {
+    pub path: PathBuf,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileError {
+    pub message: String,
+    pub location: Option<SourceLocation>,
+    pub context: Vec<(usize, String)>,
+
+    // The Markdown source of a location in converted LaTeX.
+    pub converted_from: Option<PathBuf>,
+}
+
+pub struct CompileReport {
+    pub log: LogFileReport,
+    pub errors: Vec<CompileError>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct ErrorSite {
+    message: String,
+    file: Option<String>,
+    line: Option<usize>,
+}
+
+fn unwrap_lines(log: &str) -> Vec<String> {
+    let mut lines = vec![];
+    let mut current = String::new();
+    for line in log.lines() {
+        current.push_str(line);
+        if line.chars().count() != MAX_PRINT_LINE {
+            lines.push(current.clone());
+            current.clear();
+        }
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
+
+fn looks_like_file(name: &str) -> bool {
+    !name.is_empty() && (name.contains('/') || name.contains('.'))
+}
+
+// `path/file.tex:12: message`, as printed with -file-line-error.
+fn file_line_error(line: &str) -> Option<ErrorSite> {
+    let end = line.find(".tex:")? + 4;
+    let rest = &line[end + 1..];
+    let colon = rest.find(':')?;
+    let number = rest[..colon].parse().ok()?;
+    Some(ErrorSite {
+        message: rest[colon + 1..].trim().to_owned(),
+        file: Some(line[..end].to_owned()),
+        line: Some(number),
+    })
+}
+
+// Find each error in the log and where it happened by following the files
+// TeX opens and closes, `(file` and `)`, and the `l.<n>` line after `!`.
+// Messages come from the same scan so that they cannot be paired with the
+// wrong site.
+fn scan_errors(log: &str) -> Vec<ErrorSite> {
+    let mut stack: Vec<Option<String>> = vec![];
+    let mut sites: Vec<ErrorSite> = vec![];
+    let mut pending: Option<usize> = None;
+
+    for line in unwrap_lines(log) {
+        if let Some(site) = file_line_error(&line) {
+            sites.push(site);
+            pending = Some(sites.len() - 1);
+            continue;
+        }
+
+        if let Some(message) = line.strip_prefix("! ") {
+            sites.push(ErrorSite {
+                message: message.trim().to_owned(),
+                file: stack.iter().rev().find_map(Clone::clone),
+                line: None,
+            });
+            pending = Some(sites.len() - 1);
+            continue;
+        }
+
+        // Error context echoes source text, which may have parentheses.
+        if let Some(i) = pending {
+            if let Some(rest) = line.strip_prefix("l.") {
+                let digits: String = rest.chars().take_while(char::is_ascii_digit).collect();
+                if sites[i].line.is_none() {
+                    sites[i].line = digits.parse().ok();
+                }
+                pending = None;
+            }
+            continue;
+        }
+
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '(' => {
+                    let mut name = String::new();
+                    while let Some(&n) = chars.peek() {
+                        if n.is_whitespace() || n == '(' || n == ')' {
+                            break;
+                        }
+                        name.push(n);
+                        chars.next();
+                    }
+                    stack.push(if looks_like_file(&name) {
+                        Some(name)
+                    } else {
+                        None
+                    });
+                }
+                ')' => {
+                    stack.pop();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    sites
+}
+
+fn read_context(path: &Path, line: usize) -> Vec<(usize, String)> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(_) => return vec![],
+    };
+    let first = line.saturating_sub(CONTEXT_LINES).max(1);
+    contents
+        .lines()
+        .enumerate()
+        .map(|(i, text)| (i + 1, text.to_owned()))
+        .skip(first - 1)
+        .take(line + CONTEXT_LINES + 1 - first)
+        .collect()
+}
+
+impl CompileError {
+    // `dir` is where TeX ran, locations are shown relative to the course.
+    fn new(site: &ErrorSite, dir: &Path, course: &Course) -> CompileError {
+        let location = match (&site.file, site.line) {
+            (Some(file), Some(line)) => {
+                let path = dir.join(file);
+                if path.is_file() {
+                    Some(SourceLocation { path, line })
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+
+        let context = location
+            .as_ref()
+            .map_or(vec![], |loc| read_context(&loc.path, loc.line));
+
+        // Converted Markdown mirrors the problems directory.
+        let root = &course.path;
+        let converted_from = location.as_ref().and_then(|loc| {
+            let rel = loc.path.strip_prefix(course.markdown_dir()).ok()?;
+            let source = Path::new(&course.course_file.config.sources.problems)
+                .join(rel)
+                .with_extension("md");
+            if root.join(&source).is_file() {
+                Some(source)
+            } else {
+                None
+            }
+        });
+
+        CompileError {
+            message: site.message.clone(),
+            location: location.map(|loc| SourceLocation {
+                path: loc
+                    .path
+                    .strip_prefix(root)
+                    .map(Path::to_path_buf)
+                    .unwrap_or(loc.path),
+                line: loc.line,
+            }),
+            context,
+            converted_from,
+        }
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.location {
+            Some(ref loc) => write!(f, "{}:{}: {}", loc.path.display(), loc.line, self.message)?,
+            None => write!(f, "{}", self.message)?,
+        }
+        if let Some(ref source) = self.converted_from {
+            write!(f, " (in LaTeX converted from {})", source.display())?;
+        }
+
+        let current = self.location.as_ref().map(|loc| loc.line);
+        for (number, text) in &self.context {
+            let marker = if Some(*number) == current { ">" } else { " " };
+            write!(f, "\n{} {:>5} | {}", marker, number, text)?;
+        }
+        Ok(())
+    }
+}
+
+impl CompileReport {
+    pub fn from_log(log: &str, dir: &Path, course: &Course) -> CompileReport {
+        let report = outparse::parse_log(BufReader::new(log.as_bytes()));
+        let errors = scan_errors(log)
+            .iter()
+            .map(|site| CompileError::new(site, dir, course))
+            .collect();
+
+        CompileReport {
+            log: report,
+            errors,
+        }
+    }
+
+    pub fn has_errors(&self) -> bool {
+        !self.errors.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::course::test_course;
+
+    #[test]
+    fn test_scan_errors() {
+        let log = "(./texput.tex (/usr/share/texlive/article.cls)\n\
+                   (/course/problems/limits/problem.tex\n\
+                   ! Undefined control sequence.\n\
+                   l.7 \\lim (x\n\
+                   )) (/course/problems/limits/solution.tex\n\
+                   /course/problems/limits/solution.tex:3: Missing $ inserted.\n";
+
+        assert_eq!(
+            scan_errors(log),
+            vec![
+                ErrorSite {
+                    message: String::from("Undefined control sequence."),
+                    file: Some(String::from("/course/problems/limits/problem.tex")),
+                    line: Some(7),
+                },
+                ErrorSite {
+                    message: String::from("Missing $ inserted."),
+                    file: Some(String::from("/course/problems/limits/solution.tex")),
+                    line: Some(3),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_log() {
+        let (_dir, course) = test_course(
+            "",
+            &[
+                ("problems/limits/problem.tex", "a\n\\foo\nb\n"),
+                ("problems/series/problem.md", "**x\n"),
+                (".teach/markdown/series/problem.tex", "\\textbf{x\n\n\n"),
+            ],
+        );
+        let root = &course.path;
+
+        let log = format!(
+            "(./texput.tex\n\
+             ({root}/problems/limits/problem.tex\n\
+             ! Undefined control sequence.\n\
+             l.2 \\foo\n\
+             )\n\
+             ({root}/.teach/markdown/series/problem.tex\n\
+             Runaway argument?\n\
+             {{x\n\
+             ! Paragraph ended before \\textbf was complete.\n\
+             <to be read again>\n\
+             l.2\n\
+             ))\n",
+            root = root.display()
+        );
+        let report = CompileReport::from_log(&log, root, &course);
+
+        assert_eq!(report.errors.len(), 2);
+        assert_eq!(
+            report.errors[0].to_string(),
+            "problems/limits/problem.tex:2: Undefined control sequence.\n\
+             \x20     1 | a\n\
+             >     2 | \\foo\n\
+             \x20     3 | b"
+        );
+        let error = &report.errors[1];
+        assert_eq!(
+            error.message,
+            "Paragraph ended before \\textbf was complete."
+        );
+        assert_eq!(
+            error.location,
+            Some(SourceLocation {
+                path: PathBuf::from(".teach/markdown/series/problem.tex"),
+                line: 2,
+            })
+        );
+        assert_eq!(
+            error.converted_from,
+            Some(PathBuf::from("problems/series/problem.md"))
+        );
+    }
+}
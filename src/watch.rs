@@ -0,0 +1,146 @@
+use std::path::{Component as PathComponent, Path, PathBuf};
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+use log::{error, info, trace, warn};
+use notify::{self, DebouncedEvent, RecursiveMode, Watcher};
+
+use crate::TeachResult;
+
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    Problem(String),
+    Include,
+    CourseFile,
+}
+
+impl Change {
+    pub fn affects(&self, problem: &str) -> bool {
+        match self {
+            Change::Problem(name) => name == problem,
+            _ => true,
+        }
+    }
+
+    // Titles, intros and include/ macros are in every document, so changes
+    // to them reach items without problems too.
+    pub fn affects_any(&self, problems: &[String]) -> bool {
+        match self {
+            Change::Problem(_) => problems.iter().any(|p| self.affects(p)),
+            Change::Include | Change::CourseFile => true,
+        }
+    }
+}
+
+fn watch_paths(root: &Path, problems: &str) -> Vec<PathBuf> {
+    vec![root.join(problems), root.join("include")]
+}
+
+fn is_scratch_file(path: &Path) -> bool {
+    match path.file_name() {
+        Some(name) => {
+            let name = name.to_string_lossy();
+            name.starts_with('.') || name.ends_with('~')
+        }
+        None => true,
+    }
+}
+
+pub fn classify(root: &Path, problems: &str, path: &Path) -> Option<Change> {
+    if is_scratch_file(path) {
+        return None;
+    }
+
+    if path == root.join("course.toml") {
+        return Some(Change::CourseFile);
+    }
+
+    if path.starts_with(root.join("include")) {
+        return Some(Change::Include);
+    }
+
+    if let Ok(rel) = path.strip_prefix(root.join(problems)) {
+        if let Some(PathComponent::Normal(name)) = rel.components().next() {
+            return Some(Change::Problem(name.to_string_lossy().into()));
+        }
+    }
+
+    None
+}
+
+pub fn watch<F>(root: &Path, problems: &str, mut on_change: F) -> TeachResult<()>
+where
+    F: FnMut(&Change) -> TeachResult<()>,
+{
+    let root = root.canonicalize()?;
+    let (tx, rx) = channel();
+    let mut watcher = notify::watcher(tx, Duration::from_millis(500))?;
+
+    for path in watch_paths(&root, problems) {
+        if path.exists() {
+            trace!("Watching {}", path.display());
+            watcher.watch(&path, RecursiveMode::Recursive)?;
+        }
+    }
+    // Editors that save by renaming a temporary file over course.toml
+    // would drop a watch on the file itself, so watch its directory.
+    watcher.watch(&root, RecursiveMode::NonRecursive)?;
+    info!("Watching {} for changes", root.display());
+
+    loop {
+        use DebouncedEvent::*;
+        let path = match rx.recv()? {
+            Create(p) | Write(p) | Remove(p) | Rename(_, p) => p,
+            Error(e, _) => {
+                warn!("Watch error: {}", e);
+                continue;
+            }
+            _ => continue,
+        };
+
+        if let Some(change) = classify(&root, problems, &path) {
+            info!("Detected change in {}", path.display());
+            if let Err(e) = on_change(&change) {
+                error!("{}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let root = Path::new("/course");
+        let classify = |path: &str| classify(root, "problems", Path::new(path));
+
+        assert_eq!(classify("/course/course.toml"), Some(Change::CourseFile));
+        assert_eq!(
+            classify("/course/include/macros.sty"),
+            Some(Change::Include)
+        );
+        assert_eq!(
+            classify("/course/problems/limits/solution.tex"),
+            Some(Change::Problem(String::from("limits")))
+        );
+        assert_eq!(classify("/course/problems/limits/.problem.tex.swp"), None);
+        assert_eq!(classify("/course/course.toml~"), None);
+        assert_eq!(classify("/course/.course.toml.tmp"), None);
+        assert_eq!(classify("/course/README.md"), None);
+        assert_eq!(classify("/course/problems"), None);
+    }
+
+    #[test]
+    fn test_affects() {
+        let change = Change::Problem(String::from("limits"));
+        assert!(change.affects("limits"));
+        assert!(!change.affects("series"));
+        assert!(Change::CourseFile.affects("series"));
+
+        assert!(!change.affects_any(&[]));
+        assert!(Change::CourseFile.affects_any(&[]));
+        assert!(Change::Include.affects_any(&[]));
+    }
+}